@@ -0,0 +1,171 @@
+//! Debugger for day 8 handheld programs.
+//!
+//! Reads the program from the file given as argument and commands from standard input.
+
+use std::io::{self, BufRead, Write};
+use std::{env, fs, process};
+
+use advent_of_code::day08::parsed::{Instruction, Stop, TraceEntry, Vm};
+use advent_of_code::read_lines;
+
+const HELP: &str = "\
+step [n]       execute the next n instructions (default 1)
+run            run until a breakpoint, watch, loop or termination
+until <pc>     run until reaching the instruction at pc
+break <pc>     stop before executing the instruction at pc
+delete <pc>    remove a breakpoint
+watch <acc>    stop when the accumulator changes to acc
+unwatch <acc>  remove a watch
+state          show program counter, accumulator and next instruction
+trace [n]      show the last n executed instructions (default all)
+reset          restart the program, keeping breakpoints and watches
+quit           leave the debugger";
+
+fn print_entry(entry: &TraceEntry) {
+    println!(
+        "{:>5}  {}  acc={}",
+        entry.pc, entry.instruction, entry.accumulator
+    );
+}
+
+fn print_state(vm: &Vm) {
+    println!(
+        "pc={} acc={} steps={}",
+        vm.pc(),
+        vm.accumulator(),
+        vm.steps()
+    );
+    match vm.next_instruction() {
+        Some(instruction) => println!(
+            "next: {}{}",
+            instruction,
+            if vm.has_executed(vm.pc()) {
+                " (executed before)"
+            } else {
+                ""
+            }
+        ),
+        None => println!("terminated"),
+    }
+}
+
+fn print_stop(vm: &Vm, stop: Stop) {
+    match stop {
+        Stop::Terminated => println!("terminated with acc={}", vm.accumulator()),
+        Stop::LoopDetected => println!("loop detected at pc={}", vm.pc()),
+        Stop::Breakpoint(pc) => println!("breakpoint at pc={}", pc),
+        Stop::Watch(accumulator) => println!("watch hit with acc={}", accumulator),
+        Stop::Condition => print_state(vm),
+        Stop::JumpOutOfBounds { from, to } => {
+            println!("jump out of bounds from pc={} to {}", from, to)
+        }
+        Stop::StepLimitExceeded => println!("step limit exceeded"),
+        Stop::Fault(fault) => println!("{} at pc={}", fault, vm.pc()),
+    }
+}
+
+fn argument<T: std::str::FromStr>(argument: Option<&str>) -> Result<Option<T>, String> {
+    argument
+        .map(|argument| {
+            argument
+                .parse()
+                .map_err(|_| format!("Invalid argument: {}", argument))
+        })
+        .transpose()
+}
+
+fn required<T: std::str::FromStr>(command: &str, parameter: Option<&str>) -> Result<T, String> {
+    argument(parameter)?.ok_or_else(|| format!("{} needs an argument", command))
+}
+
+fn execute(vm: &mut Vm, command: &str, parameter: Option<&str>) -> Result<bool, String> {
+    match command {
+        "step" | "s" => {
+            for _ in 0..argument(parameter)?.unwrap_or(1) {
+                match vm.step() {
+                    Some(entry) => print_entry(&entry),
+                    None => {
+                        print_stop(vm, vm.blocked().unwrap_or(Stop::Terminated));
+                        break;
+                    }
+                }
+            }
+        }
+        "run" | "r" => {
+            let stop = vm.run();
+            print_stop(vm, stop);
+        }
+        "until" => {
+            let pc: usize = required(command, parameter)?;
+            let stop = vm.run_until(|vm| vm.pc() == pc);
+            print_stop(vm, stop);
+        }
+        "break" | "b" => {
+            vm.add_breakpoint(required(command, parameter)?);
+        }
+        "delete" => {
+            if !vm.remove_breakpoint(required(command, parameter)?) {
+                println!("no such breakpoint");
+            }
+        }
+        "watch" => {
+            vm.add_watch(required(command, parameter)?);
+        }
+        "unwatch" => {
+            if !vm.remove_watch(required(command, parameter)?) {
+                println!("no such watch");
+            }
+        }
+        "state" => print_state(vm),
+        "trace" => {
+            let trace = vm.trace();
+            let count = argument(parameter)?.unwrap_or(trace.len()).min(trace.len());
+            trace[trace.len() - count..].iter().for_each(print_entry);
+        }
+        "reset" => vm.reset(),
+        "help" => println!("{}", HELP),
+        "quit" | "q" => return Ok(false),
+        _ => return Err(format!("Unknown command: {} (try help)", command)),
+    }
+    Ok(true)
+}
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| {
+        eprintln!("Usage: handheld <program>");
+        process::exit(2);
+    });
+    let program = fs::read_to_string(&path)
+        .map_err(|error| error.to_string())
+        .and_then(|input| read_lines::<Instruction>(&input).map_err(|error| error.to_string()))
+        .unwrap_or_else(|error| {
+            eprintln!("Failed to read {}: {}", path, error);
+            process::exit(1);
+        });
+    let mut vm = Vm::new(&program);
+    print_state(&vm);
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush().expect("Failed to write prompt");
+        let mut line = String::new();
+        if stdin
+            .lock()
+            .read_line(&mut line)
+            .expect("Failed to read command")
+            == 0
+        {
+            break;
+        }
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => continue,
+        };
+        match execute(&mut vm, command, words.next()) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(error) => eprintln!("{}", error),
+        }
+    }
+}
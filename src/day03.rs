@@ -1,6 +1,22 @@
-struct Slope {
-    down: usize,
-    right: usize,
+use std::cmp::Reverse;
+use std::str::FromStr;
+
+use bit_vec::BitVec;
+use itertools::Itertools;
+
+use crate::ParseError;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Slope {
+    pub down: usize,
+    pub right: isize,
+}
+
+impl Slope {
+    /// All slopes moving at most `max_right` to the right and between 1 and `max_down` down per step.
+    pub fn within(max_right: isize, max_down: usize) -> impl Iterator<Item = Slope> {
+        (1..=max_down).flat_map(move |down| (0..=max_right).map(move |right| Slope { down, right }))
+    }
 }
 
 const SLOPES: &[Slope] = &[
@@ -11,32 +27,160 @@ const SLOPES: &[Slope] = &[
     Slope { right: 1, down: 2 },
 ];
 
-fn count_trees(input: &str, slope: &Slope) -> usize {
-    input
-        .lines()
-        .step_by(slope.down)
-        .enumerate()
-        .filter(|(step, row)| row.chars().cycle().nth(step * slope.right).unwrap() == '#')
-        .count()
+#[derive(Debug, Clone, PartialEq)]
+pub struct Forest {
+    width: usize,
+    rows: Vec<BitVec>,
+}
+
+impl Forest {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Checks for a tree, repeating the map infinitely to both sides. Rows below the map have no trees.
+    pub fn is_tree(&self, row: usize, column: isize) -> bool {
+        match self.rows.get(row) {
+            Some(tiles) => tiles[column.rem_euclid(self.width as isize) as usize],
+            None => false,
+        }
+    }
+
+    /// Positions visited when starting at the top left corner, one per step until leaving the bottom.
+    /// Slopes without downward movement never leave, so they have no path.
+    pub fn path(&self, slope: &Slope) -> impl Iterator<Item = (usize, isize)> + '_ {
+        let slope = *slope;
+        let rows = if slope.down > 0 { self.height() } else { 0 };
+        (0..rows)
+            .step_by(slope.down.max(1))
+            .enumerate()
+            .map(move |(step, row)| (row, step as isize * slope.right))
+    }
+
+    pub fn count_trees(&self, slope: &Slope) -> usize {
+        self.path(slope)
+            .filter(|&(row, column)| self.is_tree(row, column))
+            .count()
+    }
+
+    /// Tree collisions along the slope as (row, column) pairs, with columns wrapped into the map.
+    pub fn tree_collisions(&self, slope: &Slope) -> Vec<(usize, usize)> {
+        self.path(slope)
+            .filter(|&(row, column)| self.is_tree(row, column))
+            .map(|(row, column)| (row, column.rem_euclid(self.width as isize) as usize))
+            .collect()
+    }
+
+    /// Renders the map with `O` for open squares and `X` for trees hit along the slope,
+    /// repeating the map horizontally as often as the path requires.
+    pub fn render_path(&self, slope: &Slope) -> String {
+        let mut visited = vec![None; self.height()];
+        for (row, column) in self.path(slope) {
+            visited[row] = Some(column);
+        }
+        let width = self.width as isize;
+        let (first, last) = visited
+            .iter()
+            .flatten()
+            .fold((0, 0), |(first, last), column| {
+                (first.min(*column), last.max(*column))
+            });
+        let columns = first.div_euclid(width) * width..(last.div_euclid(width) + 1) * width;
+        visited
+            .iter()
+            .enumerate()
+            .map(|(row, visited)| {
+                columns
+                    .clone()
+                    .map(
+                        |column| match (self.is_tree(row, column), *visited == Some(column)) {
+                            (true, true) => 'X',
+                            (false, true) => 'O',
+                            (true, false) => '#',
+                            (false, false) => '.',
+                        },
+                    )
+                    .collect::<String>()
+            })
+            .join("\n")
+    }
+
+    fn tree_counts<'a, I>(&'a self, slopes: I) -> impl Iterator<Item = (Slope, usize)> + 'a
+    where
+        I: IntoIterator<Item = Slope>,
+        I::IntoIter: 'a,
+    {
+        slopes
+            .into_iter()
+            .filter(|slope| slope.down > 0)
+            .map(move |slope| (slope, self.count_trees(&slope)))
+    }
+
+    /// Finds the slope hitting the fewest trees, preferring the smallest slope on ties.
+    pub fn fewest_trees(&self, slopes: impl IntoIterator<Item = Slope>) -> Option<(Slope, usize)> {
+        self.tree_counts(slopes)
+            .min_by_key(|&(slope, trees)| (trees, slope))
+    }
+
+    /// Finds the slope hitting the most trees, preferring the smallest slope on ties.
+    pub fn most_trees(&self, slopes: impl IntoIterator<Item = Slope>) -> Option<(Slope, usize)> {
+        self.tree_counts(slopes)
+            .max_by_key(|&(slope, trees)| (trees, Reverse(slope)))
+    }
+}
+
+impl FromStr for Forest {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut width = None;
+        let mut rows = Vec::new();
+        for line in input.lines() {
+            if line.is_empty() || *width.get_or_insert(line.len()) != line.len() {
+                return Err(ParseError::FormatError);
+            }
+            rows.push(
+                line.bytes()
+                    .map(|tile| match tile {
+                        b'#' => Ok(true),
+                        b'.' => Ok(false),
+                        _ => Err(ParseError::FormatError),
+                    })
+                    .collect::<Result<BitVec, _>>()?,
+            );
+        }
+        Ok(Forest {
+            width: width.ok_or(ParseError::Missing("rows"))?,
+            rows,
+        })
+    }
+}
+
+#[aoc_generator(day3)]
+fn read_forest(input: &str) -> Result<Forest, ParseError> {
+    input.parse()
 }
 
 #[aoc(day3, part1)]
-fn part1(input: &str) -> usize {
-    count_trees(input, &Slope { right: 3, down: 1 })
+fn part1(forest: &Forest) -> usize {
+    forest.count_trees(&Slope { right: 3, down: 1 })
 }
 
 #[aoc(day3, part2)]
-fn part2(input: &str) -> usize {
+fn part2(forest: &Forest) -> usize {
     SLOPES
         .iter()
-        .map(|slope| count_trees(input, slope))
+        .map(|slope| forest.count_trees(slope))
         .product()
 }
 
 #[cfg(test)]
 mod should {
     use super::*;
-    use itertools::Itertools;
 
     const EXAMPLE: &str = "\
 ..##.......
@@ -53,28 +197,149 @@ mod should {
 
     const INPUT: &str = include_str!("../input/2020/day3.txt");
 
+    fn solve(solver: fn(&Forest) -> usize, input: &str) -> Result<usize, ParseError> {
+        read_forest(input).map(|forest| solver(&forest))
+    }
+
     #[test]
     fn count_7_trees_in_example_part1() {
-        assert_eq!(part1(EXAMPLE), 7);
+        assert_eq!(solve(part1, EXAMPLE), Ok(7));
     }
 
     #[test]
     fn solves_part1() {
-        assert_eq!(part1(INPUT), 234);
+        assert_eq!(solve(part1, INPUT), Ok(234));
+    }
+
+    #[test]
+    fn solves_part2() {
+        assert_eq!(solve(part2, INPUT), Ok(5813773056));
     }
 
     #[test]
     fn count_trees_on_slopes() {
+        let forest = EXAMPLE.parse::<Forest>().expect("Failed to parse example");
         assert_eq!(
             SLOPES
                 .iter()
-                .map(|slope| count_trees(EXAMPLE, slope))
+                .map(|slope| forest.count_trees(slope))
                 .collect_vec(),
             &[2, 7, 3, 4, 2]
         );
     }
+
     #[test]
-    fn solves_part2() {
-        assert_eq!(part2(INPUT), 5813773056);
+    fn finds_extreme_slopes_in_example() {
+        let forest = EXAMPLE.parse::<Forest>().expect("Failed to parse example");
+        assert_eq!(
+            forest.most_trees(SLOPES.iter().copied()),
+            Some((Slope { right: 3, down: 1 }, 7))
+        );
+        assert_eq!(
+            forest.fewest_trees(SLOPES.iter().copied()),
+            Some((Slope { right: 1, down: 1 }, 2))
+        );
+    }
+
+    #[test]
+    fn breaks_ties_by_smallest_slope() {
+        let forest = "..\n..\n.."
+            .parse::<Forest>()
+            .expect("Failed to parse forest");
+        assert_eq!(
+            forest.fewest_trees(Slope::within(2, 2)),
+            Some((Slope { right: 0, down: 1 }, 0))
+        );
+        assert_eq!(
+            forest.most_trees(Slope::within(2, 2)),
+            Some((Slope { right: 0, down: 1 }, 0))
+        );
+    }
+
+    #[test]
+    fn ignores_slopes_without_downward_movement() {
+        let forest = EXAMPLE.parse::<Forest>().expect("Failed to parse example");
+        let flat = Slope { right: 1, down: 0 };
+        assert_eq!(forest.most_trees(vec![flat]), None);
+        assert_eq!(forest.path(&flat).count(), 0);
+        assert_eq!(forest.count_trees(&flat), 0);
+        assert!(forest.tree_collisions(&flat).is_empty());
+        assert_eq!(forest.render_path(&flat).lines().count(), forest.height());
+    }
+
+    #[test]
+    fn has_no_trees_below_the_map() {
+        let forest = EXAMPLE.parse::<Forest>().expect("Failed to parse example");
+        assert!(!forest.is_tree(forest.height(), 2));
+        assert!(!forest.is_tree(usize::MAX, 0));
+    }
+
+    #[test]
+    fn wraps_columns_in_both_directions() {
+        let forest = EXAMPLE.parse::<Forest>().expect("Failed to parse example");
+        assert!(forest.is_tree(0, 2));
+        assert!(forest.is_tree(0, 13));
+        assert!(forest.is_tree(0, -8));
+        assert!(!forest.is_tree(0, -1));
+    }
+
+    #[test]
+    fn counts_trees_on_leftward_slopes() {
+        let forest = EXAMPLE.parse::<Forest>().expect("Failed to parse example");
+        assert_eq!(forest.count_trees(&Slope { right: -3, down: 1 }), 3);
+        assert_eq!(forest.count_trees(&Slope { right: -1, down: 2 }), 2);
+    }
+
+    #[test]
+    fn rejects_ragged_rows() {
+        assert_eq!("..#\n.#".parse::<Forest>(), Err(ParseError::FormatError));
+    }
+
+    #[test]
+    fn rejects_unknown_tiles() {
+        assert_eq!("..#\n.O.".parse::<Forest>(), Err(ParseError::FormatError));
+    }
+
+    #[test]
+    fn renders_path_over_repeated_map() {
+        let forest = EXAMPLE.parse::<Forest>().expect("Failed to parse example");
+        assert_eq!(
+            forest.render_path(&Slope { right: 3, down: 1 }),
+            "\
+O.##.........##.........##.......
+#..O#...#..#...#...#..#...#...#..
+.#....X..#..#....#..#..#....#..#.
+..#.#...#O#..#.#...#.#..#.#...#.#
+.#...##..#..X...##..#..#...##..#.
+..#.##.......#.X#.......#.##.....
+.#.#.#....#.#.#.#.O..#.#.#.#....#
+.#........#.#........X.#........#
+#.##...#...#.##...#...#.X#...#...
+#...##....##...##....##...#X....#
+.#..#...#.#.#..#...#.#.#..#...X.#"
+        );
+    }
+
+    #[test]
+    fn renders_leftward_path_and_skipped_rows() {
+        let forest = "..#\n#..\n.#."
+            .parse::<Forest>()
+            .expect("Failed to parse forest");
+        assert_eq!(
+            forest.render_path(&Slope { right: -1, down: 2 }),
+            "\
+..#O.#
+#..#..
+.#O.#."
+        );
+    }
+
+    #[test]
+    fn lists_tree_collisions() {
+        let forest = EXAMPLE.parse::<Forest>().expect("Failed to parse example");
+        assert_eq!(
+            forest.tree_collisions(&Slope { right: 3, down: 1 }),
+            vec![(2, 6), (4, 1), (5, 4), (7, 10), (8, 2), (9, 5), (10, 8)]
+        );
     }
 }
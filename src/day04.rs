@@ -1,95 +1,415 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
 use itertools::Itertools;
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::collections::HashMap;
-use std::ops::RangeBounds;
+use thiserror::Error;
 
-struct Passport(HashMap<String, String>);
+use crate::ParseError;
 
-impl Passport {
-    const REQUIRED_KEYS: &'static [&'static str] = &[
-        "byr", // (Birth Year)
-        "iyr", // (Issue Year)
-        "eyr", // (Expiration Year)
-        "hgt", // (Height)
-        "hcl", // (Hair Color)
-        "ecl", // (Eye Color)
-        "pid", // (Passport ID)
-               // "cid", // (Country ID)
-    ];
+#[derive(Debug, Error, PartialEq)]
+pub enum SchemaError {
+    #[error(transparent)]
+    ParseError(#[from] ParseError),
+    #[error("Unknown presence: {0}")]
+    UnknownPresence(String),
+    #[error("Unknown rule: {0}")]
+    UnknownRule(String),
+    #[error(transparent)]
+    InvalidRegex(#[from] regex::Error),
+}
 
-    fn has_required_fields(&self) -> bool {
-        Self::REQUIRED_KEYS
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RecordError {
+    #[error("Empty record")]
+    Empty,
+    #[error("Malformed field: {0}")]
+    MalformedField(String),
+    #[error("Duplicate field: {0}")]
+    DuplicateField(String),
+    #[error("Unknown field: {0}")]
+    UnknownField(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Rejects records with malformed, duplicate or unknown fields.
+    Strict,
+    /// Skips malformed fields and lets later duplicates win, accepting any record.
+    Lenient,
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum Violation {
+    #[error("missing field `{0}`")]
+    Missing(String),
+    #[error("`{key}` `{value}` is not a number")]
+    NotANumber { key: String, value: String },
+    #[error("`{key}` {value} below min {min}{unit}")]
+    BelowMin {
+        key: String,
+        value: String,
+        min: u32,
+        unit: String,
+    },
+    #[error("`{key}` {value} above max {max}{unit}")]
+    AboveMax {
+        key: String,
+        value: String,
+        max: u32,
+        unit: String,
+    },
+    #[error("`{key}` `{value}` has none of the units {}", .units.join(", "))]
+    MissingUnit {
+        key: String,
+        value: String,
+        units: Vec<String>,
+    },
+    #[error("`{key}` `{value}` is not one of {}", .allowed.join(", "))]
+    NotAllowed {
+        key: String,
+        value: String,
+        allowed: Vec<String>,
+    },
+    #[error("`{key}` `{value}` does not match {description}")]
+    NoMatch {
+        key: String,
+        value: String,
+        description: String,
+    },
+}
+
+impl Violation {
+    pub fn key(&self) -> &str {
+        match self {
+            Violation::Missing(key)
+            | Violation::NotANumber { key, .. }
+            | Violation::BelowMin { key, .. }
+            | Violation::AboveMax { key, .. }
+            | Violation::MissingUnit { key, .. }
+            | Violation::NotAllowed { key, .. }
+            | Violation::NoMatch { key, .. } => key,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Rule {
+    Any,
+    Range(RangeInclusive<u32>),
+    Units(Vec<(String, RangeInclusive<u32>)>),
+    Enum(Vec<String>),
+    Regex { regex: Regex, description: String },
+}
+
+impl Rule {
+    pub fn accepts(&self, value: &str) -> bool {
+        self.check("", value).is_ok()
+    }
+
+    pub fn check(&self, key: &str, value: &str) -> Result<(), Violation> {
+        match self {
+            Rule::Any => Ok(()),
+            Rule::Range(range) => check_range(key, value, value, range, ""),
+            Rule::Units(units) => units
+                .iter()
+                .find_map(|(unit, range)| {
+                    value
+                        .strip_suffix(unit.as_str())
+                        .map(|number| check_range(key, value, number, range, unit))
+                })
+                .unwrap_or_else(|| {
+                    Err(Violation::MissingUnit {
+                        key: key.to_string(),
+                        value: value.to_string(),
+                        units: units.iter().map(|(unit, _)| unit.clone()).collect(),
+                    })
+                }),
+            Rule::Enum(allowed) if allowed.iter().any(|allowed| allowed == value) => Ok(()),
+            Rule::Enum(allowed) => Err(Violation::NotAllowed {
+                key: key.to_string(),
+                value: value.to_string(),
+                allowed: allowed.clone(),
+            }),
+            Rule::Regex { regex, .. } if regex.is_match(value) => Ok(()),
+            Rule::Regex { description, .. } => Err(Violation::NoMatch {
+                key: key.to_string(),
+                value: value.to_string(),
+                description: description.clone(),
+            }),
+        }
+    }
+}
+
+fn check_range(
+    key: &str,
+    value: &str,
+    number: &str,
+    range: &RangeInclusive<u32>,
+    unit: &str,
+) -> Result<(), Violation> {
+    let number = number.parse::<u32>().map_err(|_| Violation::NotANumber {
+        key: key.to_string(),
+        value: value.to_string(),
+    })?;
+    if number < *range.start() {
+        Err(Violation::BelowMin {
+            key: key.to_string(),
+            value: value.to_string(),
+            min: *range.start(),
+            unit: unit.to_string(),
+        })
+    } else if number > *range.end() {
+        Err(Violation::AboveMax {
+            key: key.to_string(),
+            value: value.to_string(),
+            max: *range.end(),
+            unit: unit.to_string(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = SchemaError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (kind, arguments) = split_word(input);
+        Ok(match kind {
+            "" => Rule::Any,
+            "range" => {
+                let (min, max) = arguments
+                    .split_whitespace()
+                    .collect_tuple()
+                    .ok_or(ParseError::Missing("range bounds"))?;
+                Rule::Range(read_range(min, max)?)
+            }
+            "units" => {
+                let arguments = arguments.split_whitespace().collect_vec();
+                if arguments.is_empty() || arguments.len() % 3 != 0 {
+                    return Err(ParseError::Missing("unit bounds").into());
+                }
+                Rule::Units(
+                    arguments
+                        .iter()
+                        .tuples()
+                        .map(|(unit, min, max)| Ok((unit.to_string(), read_range(min, max)?)))
+                        .collect::<Result<_, ParseError>>()?,
+                )
+            }
+            "enum" => Rule::Enum(arguments.split_whitespace().map(String::from).collect()),
+            "regex" => {
+                let (pattern, description) = match arguments.rsplitn(2, " as ").collect_tuple() {
+                    Some((description, pattern)) => (pattern.trim_end(), description.trim_start()),
+                    None => (arguments, arguments),
+                };
+                Rule::Regex {
+                    regex: Regex::new(pattern)?,
+                    description: description.to_string(),
+                }
+            }
+            _ => return Err(SchemaError::UnknownRule(kind.to_string())),
+        })
+    }
+}
+
+fn read_range(min: &str, max: &str) -> Result<RangeInclusive<u32>, ParseError> {
+    Ok(min.parse()?..=max.parse()?)
+}
+
+fn split_word(input: &str) -> (&str, &str) {
+    let input = input.trim();
+    match input.find(char::is_whitespace) {
+        Some(end) => (&input[..end], input[end..].trim_start()),
+        None => (input, ""),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldRule {
+    pub key: String,
+    pub required: bool,
+    pub rule: Rule,
+}
+
+impl FromStr for FieldRule {
+    type Err = SchemaError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (key, rest) = split_word(input);
+        let (presence, rule) = split_word(rest);
+        let required = match presence {
+            "required" => true,
+            "optional" => false,
+            "" => return Err(ParseError::Missing("presence").into()),
+            _ => return Err(SchemaError::UnknownPresence(presence.to_string())),
+        };
+        Ok(FieldRule {
+            key: key.to_string(),
+            required,
+            rule: rule.parse()?,
+        })
+    }
+}
+
+/// The fields of a document type and the rules their values must follow.
+///
+/// Schemas are read line by line, one field per line, skipping blank lines and `#` comments.
+/// See `day04.schema` for the rules shipped for passports.
+#[derive(Debug, Clone)]
+pub struct Schema {
+    fields: Vec<FieldRule>,
+}
+
+impl Schema {
+    pub fn fields(&self) -> &[FieldRule] {
+        &self.fields
+    }
+
+    pub fn field(&self, key: &str) -> Option<&FieldRule> {
+        self.fields.iter().find(|field| field.key == key)
+    }
+
+    pub fn has_required_fields(&self, passport: &Passport) -> bool {
+        self.fields
             .iter()
-            .all(|key| self.0.contains_key(*key))
+            .filter(|field| field.required)
+            .all(|field| passport.0.contains_key(&field.key))
     }
 
-    fn is_valid(&self) -> bool {
-        self.has_required_fields()
-            && self.check_field("pid", is_valid_passport_id)
-            && self.check_field("ecl", is_valid_eye_color)
-            && self.check_field("hcl", is_valid_hair_color)
-            && self.check_field("hgt", is_valid_height)
-            // eyr (Expiration Year) - four digits; at least 2020 and at most 2030.
-            && self.check_field("eyr", |year| is_number_in_range(year, 2020..=2030))
-            // iyr (Issue Year) - four digits; at least 2010 and at most 2020.
-            && self.check_field("iyr", |year| is_number_in_range(year, 2010..=2020))
-            // byr (Birth Year) - four digits; at least 1920 and at most 2002.
-            && self.check_field("byr", |year| is_number_in_range(year, 1920..=2002))
-    }
-
-    fn check_field<P>(&self, name: &str, condition: P) -> bool
-    where
-        P: Fn(&str) -> bool,
-    {
-        self.0
-            .get(name)
-            .map(|value| condition(value))
-            .unwrap_or(false)
+    pub fn is_valid(&self, passport: &Passport) -> bool {
+        self.fields
+            .iter()
+            .all(|field| self.check_field(field, passport).is_ok())
+    }
+
+    /// Lists every rule the passport breaks, in schema order.
+    pub fn validate(&self, passport: &Passport) -> Vec<Violation> {
+        self.fields
+            .iter()
+            .filter_map(|field| self.check_field(field, passport).err())
+            .collect()
+    }
+
+    /// Validates every passport record in the input.
+    pub fn validate_all(&self, input: &str) -> Vec<PassportReport> {
+        split_records(input)
+            .enumerate()
+            .map(|(index, (lines, record))| PassportReport {
+                index,
+                lines,
+                violations: self.validate(&Passport::read_lenient(record)),
+            })
+            .collect()
+    }
+
+    /// Reads a single passport record, rejecting keys outside of this schema in strict mode.
+    pub fn read_passport(&self, record: &str, mode: ParseMode) -> Result<Passport, RecordError> {
+        if mode == ParseMode::Lenient {
+            return Ok(Passport::read_lenient(record));
+        }
+        let mut fields = HashMap::new();
+        for token in record.split_whitespace() {
+            let (key, value) = match token.splitn(2, ':').collect_tuple() {
+                Some((key, value)) if !key.is_empty() && !value.is_empty() => (key, value),
+                _ => return Err(RecordError::MalformedField(token.to_string())),
+            };
+            if self.field(key).is_none() {
+                return Err(RecordError::UnknownField(key.to_string()));
+            }
+            if fields.insert(key.to_string(), value.to_string()).is_some() {
+                return Err(RecordError::DuplicateField(key.to_string()));
+            }
+        }
+        if fields.is_empty() {
+            return Err(RecordError::Empty);
+        }
+        Ok(Passport(fields))
+    }
+
+    fn check_field(&self, field: &FieldRule, passport: &Passport) -> Result<(), Violation> {
+        match passport.0.get(&field.key) {
+            Some(value) => field.rule.check(&field.key, value),
+            None if field.required => Err(Violation::Missing(field.key.clone())),
+            None => Ok(()),
+        }
     }
 }
 
-// pid (Passport ID) - a nine-digit number, including leading zeroes.
-fn is_valid_passport_id(pid: &str) -> bool {
-    lazy_static! {
-        static ref RE: Regex = Regex::new("^[0-9]{9}$").unwrap();
+impl FromStr for Schema {
+    type Err = SchemaError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Ok(Schema {
+            fields: input
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::parse)
+                .collect::<Result<_, _>>()?,
+        })
     }
-    RE.is_match(pid)
 }
 
-// ecl (Eye Color) - exactly one of: amb blu brn gry grn hzl oth.
-fn is_valid_eye_color(color: &str) -> bool {
-    matches!(color, "amb" | "blu" | "brn" | "gry" | "grn" | "hzl" | "oth")
+/// The violations of a single passport, with its index and the (1-based) lines it spans in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassportReport {
+    pub index: usize,
+    pub lines: RangeInclusive<usize>,
+    pub violations: Vec<Violation>,
 }
 
-// hcl (Hair Color) - a # followed by exactly six characters 0-9 or a-f.
-fn is_valid_hair_color(color: &str) -> bool {
-    lazy_static! {
-        static ref RE: Regex = Regex::new("^#[0-9a-f]{6}$").unwrap();
+impl PassportReport {
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
     }
-    RE.is_match(color)
 }
 
-// hgt (Height) - a number followed by either cm or in:
-// If cm, the number must be at least 150 and at most 193.
-// If in, the number must be at least 59 and at most 76.
-fn is_valid_height(height: &str) -> bool {
-    match height.split_at(height.len() - 2) {
-        (cm, "cm") => is_number_in_range(cm, 150..=193),
-        (inch, "in") => is_number_in_range(inch, 59..=76),
-        _ => false,
+impl Display for PassportReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "passport {} (lines {}-{}): ",
+            self.index,
+            self.lines.start(),
+            self.lines.end()
+        )?;
+        if self.is_valid() {
+            write!(f, "valid")
+        } else {
+            write!(f, "{}", self.violations.iter().join("; "))
+        }
     }
 }
 
-fn is_number_in_range(input: &str, range: impl RangeBounds<u32>) -> bool {
-    input
-        .parse::<u32>()
-        .map(|number| range.contains(&number))
-        .unwrap_or(false)
+/// Counts the passports failing each field.
+pub fn summarize(reports: &[PassportReport]) -> BTreeMap<String, usize> {
+    let mut failures = BTreeMap::new();
+    for report in reports {
+        for key in report.violations.iter().map(Violation::key).unique() {
+            *failures.entry(key.to_string()).or_insert(0) += 1;
+        }
+    }
+    failures
 }
 
-impl<'a> From<&'a str> for Passport {
-    fn from(input: &'a str) -> Self {
+lazy_static! {
+    pub static ref PASSPORT_SCHEMA: Schema = include_str!("day04.schema")
+        .parse()
+        .expect("Invalid default passport schema");
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Passport(HashMap<String, String>);
+
+impl Passport {
+    /// Reads a record the forgiving way: tokens without a `:` are dropped and repeated keys overwrite earlier ones.
+    pub fn read_lenient(input: &str) -> Self {
         Passport(
             input
                 .split_whitespace()
@@ -97,11 +417,386 @@ impl<'a> From<&'a str> for Passport {
                 .collect(),
         )
     }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// The fields of the passport, ordered by key.
+    pub fn sorted_fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .sorted()
+    }
+
+    fn has_required_fields(&self) -> bool {
+        PASSPORT_SCHEMA.has_required_fields(self)
+    }
+
+    fn is_valid(&self) -> bool {
+        PASSPORT_SCHEMA.is_valid(self)
+    }
+}
+
+impl<'a> TryFrom<&'a str> for Passport {
+    type Error = RecordError;
+
+    fn try_from(input: &'a str) -> Result<Self, Self::Error> {
+        PASSPORT_SCHEMA.read_passport(input, ParseMode::Strict)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Height {
+    Cm(u16),
+    In(u16),
+}
+
+impl Height {
+    pub fn centimetres(&self) -> f64 {
+        match self {
+            Height::Cm(cm) => f64::from(*cm),
+            Height::In(inches) => f64::from(*inches) * 2.54,
+        }
+    }
+}
+
+impl FromStr for Height {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if let Some(cm) = input.strip_suffix("cm") {
+            Ok(Height::Cm(cm.parse()?))
+        } else if let Some(inches) = input.strip_suffix("in") {
+            Ok(Height::In(inches.parse()?))
+        } else {
+            Err(ParseError::Missing("unit"))
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl FromStr for Rgb {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let hex = input.strip_prefix('#').ok_or(ParseError::Missing("#"))?;
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(ParseError::FormatError);
+        }
+        let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16);
+        Ok(Rgb {
+            red: channel(0)?,
+            green: channel(2)?,
+            blue: channel(4)?,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum EyeColor {
+    Amber,
+    Blue,
+    Brown,
+    Gray,
+    Green,
+    Hazel,
+    Other,
+}
+
+impl FromStr for EyeColor {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Ok(match input {
+            "amb" => EyeColor::Amber,
+            "blu" => EyeColor::Blue,
+            "brn" => EyeColor::Brown,
+            "gry" => EyeColor::Gray,
+            "grn" => EyeColor::Green,
+            "hzl" => EyeColor::Hazel,
+            "oth" => EyeColor::Other,
+            _ => return Err(ParseError::FormatError),
+        })
+    }
+}
+
+/// A passport that passed validation against the default schema, with its fields parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidatedPassport {
+    birth_year: u16,
+    issue_year: u16,
+    expiration_year: u16,
+    height: Height,
+    hair_color: Rgb,
+    eye_color: EyeColor,
+    passport_id: String,
+    country_id: Option<String>,
+}
+
+impl ValidatedPassport {
+    pub fn birth_year(&self) -> u16 {
+        self.birth_year
+    }
+
+    pub fn issue_year(&self) -> u16 {
+        self.issue_year
+    }
+
+    pub fn expiration_year(&self) -> u16 {
+        self.expiration_year
+    }
+
+    pub fn height(&self) -> Height {
+        self.height
+    }
+
+    pub fn hair_color(&self) -> Rgb {
+        self.hair_color
+    }
+
+    pub fn eye_color(&self) -> EyeColor {
+        self.eye_color
+    }
+
+    pub fn passport_id(&self) -> &str {
+        &self.passport_id
+    }
+
+    pub fn country_id(&self) -> Option<&str> {
+        self.country_id.as_deref()
+    }
+}
+
+impl<'a> TryFrom<&'a Passport> for ValidatedPassport {
+    type Error = Vec<Violation>;
+
+    fn try_from(passport: &'a Passport) -> Result<Self, Self::Error> {
+        let violations = PASSPORT_SCHEMA.validate(passport);
+        if !violations.is_empty() {
+            return Err(violations);
+        }
+        read_validated(passport).map_err(|violation| vec![violation])
+    }
+}
+
+fn read_validated(passport: &Passport) -> Result<ValidatedPassport, Violation> {
+    Ok(ValidatedPassport {
+        birth_year: parse_field(passport, "byr", "a year")?,
+        issue_year: parse_field(passport, "iyr", "a year")?,
+        expiration_year: parse_field(passport, "eyr", "a year")?,
+        height: parse_field(passport, "hgt", "a height")?,
+        hair_color: parse_field(passport, "hcl", "a color")?,
+        eye_color: parse_field(passport, "ecl", "an eye color")?,
+        passport_id: parse_field(passport, "pid", "a passport id")?,
+        country_id: passport.get("cid").map(String::from),
+    })
+}
+
+/// Parses a field the schema has accepted, in case the schema is more permissive than the type.
+fn parse_field<T: FromStr>(
+    passport: &Passport,
+    key: &str,
+    description: &str,
+) -> Result<T, Violation> {
+    let value = passport
+        .get(key)
+        .ok_or_else(|| Violation::Missing(key.to_string()))?;
+    value.parse().map_err(|_| Violation::NoMatch {
+        key: key.to_string(),
+        value: value.to_string(),
+        description: description.to_string(),
+    })
+}
+
+/// All passports passing validation, skipping the others.
+pub fn validated_passports(passports: &[Passport]) -> Vec<ValidatedPassport> {
+    passports
+        .iter()
+        .filter_map(|passport| ValidatedPassport::try_from(passport).ok())
+        .collect()
+}
+
+/// Splits the input into blank line separated records, along with the lines each record spans.
+/// Blank input has no records.
+fn split_records(input: &str) -> impl Iterator<Item = (RangeInclusive<usize>, &str)> {
+    let blank = input.trim().is_empty();
+    let mut line = 1;
+    input
+        .split("\n\n")
+        .filter(move |_| !blank)
+        .map(move |record| {
+            let start = line + record.len() - record.trim_start_matches('\n').len();
+            let end = start + record.trim_matches('\n').lines().count().max(1) - 1;
+            line += record.matches('\n').count() + 2;
+            (start..=end, record)
+        })
 }
 
 #[aoc_generator(day4)]
 fn read_passports(input: &str) -> Vec<Passport> {
-    input.split("\n\n").map(Passport::from).collect_vec()
+    split_records(input)
+        .map(|(_, record)| Passport::read_lenient(record))
+        .collect_vec()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// Blank line separated records of whitespace separated `key:value` fields.
+    Native,
+    /// An array of objects mapping keys to string (or number) values.
+    Json,
+    /// A header row of keys, one row per passport and empty cells for missing fields.
+    Csv,
+}
+
+#[derive(Debug, Error)]
+pub enum ConversionError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error("Unexpected JSON: {0}")]
+    UnexpectedJson(String),
+    #[error("Cannot write {key}:{value:?} as {format:?}")]
+    UnrepresentableField {
+        format: Format,
+        key: String,
+        value: String,
+    },
+    #[error("Cannot write passport {index} without fields as {format:?}")]
+    EmptyPassport { format: Format, index: usize },
+}
+
+/// Checks that reading the passports back in the format gives the same passports.
+fn check_representable(passports: &[Passport], format: Format) -> Result<(), ConversionError> {
+    for (index, passport) in passports.iter().enumerate() {
+        if format == Format::Native && passport.0.is_empty() {
+            return Err(ConversionError::EmptyPassport { format, index });
+        }
+        for (key, value) in passport.sorted_fields() {
+            let representable = match format {
+                Format::Native => {
+                    !key.contains(|c: char| c == ':' || c.is_whitespace())
+                        && !value.is_empty()
+                        && !value.contains(char::is_whitespace)
+                }
+                Format::Json => true,
+                Format::Csv => !value.is_empty(),
+            };
+            if !representable {
+                return Err(ConversionError::UnrepresentableField {
+                    format,
+                    key: key.to_string(),
+                    value: value.to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn read_passports_as(input: &str, format: Format) -> Result<Vec<Passport>, ConversionError> {
+    match format {
+        Format::Native => Ok(read_passports(input)),
+        Format::Json => read_json(input),
+        Format::Csv => read_csv(input),
+    }
+}
+
+pub fn write_passports_as(
+    passports: &[Passport],
+    format: Format,
+) -> Result<String, ConversionError> {
+    check_representable(passports, format)?;
+    match format {
+        Format::Native => Ok(passports
+            .iter()
+            .map(|passport| {
+                passport
+                    .sorted_fields()
+                    .map(|(key, value)| format!("{}:{}", key, value))
+                    .join(" ")
+            })
+            .join("\n\n")),
+        Format::Json => Ok(serde_json::to_string_pretty(
+            &passports
+                .iter()
+                .map(|passport| passport.sorted_fields().collect::<BTreeMap<_, _>>())
+                .collect_vec(),
+        )?),
+        Format::Csv => write_csv(passports),
+    }
+}
+
+fn read_json(input: &str) -> Result<Vec<Passport>, ConversionError> {
+    match serde_json::from_str(input)? {
+        serde_json::Value::Array(passports) => {
+            passports.into_iter().map(read_json_passport).collect()
+        }
+        other => Err(ConversionError::UnexpectedJson(other.to_string())),
+    }
+}
+
+fn read_json_passport(passport: serde_json::Value) -> Result<Passport, ConversionError> {
+    match passport {
+        serde_json::Value::Object(fields) => Ok(Passport(
+            fields
+                .into_iter()
+                .map(|(key, value)| match value {
+                    serde_json::Value::String(value) => Ok((key, value)),
+                    serde_json::Value::Number(value) => Ok((key, value.to_string())),
+                    other => Err(ConversionError::UnexpectedJson(other.to_string())),
+                })
+                .collect::<Result<_, _>>()?,
+        )),
+        other => Err(ConversionError::UnexpectedJson(other.to_string())),
+    }
+}
+
+fn read_csv(input: &str) -> Result<Vec<Passport>, ConversionError> {
+    let mut reader = csv::Reader::from_reader(input.as_bytes());
+    let keys = reader.headers()?.clone();
+    reader
+        .records()
+        .map(|record| {
+            Ok(Passport(
+                keys.iter()
+                    .zip(record?.iter())
+                    .filter(|(_, value)| !value.is_empty())
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            ))
+        })
+        .collect()
+}
+
+fn write_csv(passports: &[Passport]) -> Result<String, ConversionError> {
+    let keys = passports
+        .iter()
+        .flat_map(|passport| passport.0.keys())
+        .sorted()
+        .dedup()
+        .collect_vec();
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(&keys)?;
+    for passport in passports {
+        writer.write_record(
+            keys.iter()
+                .map(|key| passport.0.get(*key).map(String::as_str).unwrap_or("")),
+        )?;
+    }
+    let output = writer
+        .into_inner()
+        .map_err(|error| csv::Error::from(error.into_error()))?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
 }
 
 #[aoc(day4, part1)]
@@ -156,37 +851,85 @@ iyr:2011 ecl:brn hgt:59in";
         assert_eq!(part2(&read_passports(INPUT)), 179);
     }
 
+    fn accepts(key: &str, value: &str) -> bool {
+        PASSPORT_SCHEMA
+            .field(key)
+            .map(|field| field.rule.accepts(value))
+            .expect("Missing field in default schema")
+    }
+
     #[test]
     fn validates_heigt() {
-        assert!(is_valid_height("60in"));
-        assert!(is_valid_height("190cm"));
-        assert!(!is_valid_height("190in"));
-        assert!(!is_valid_height("190"));
+        assert!(accepts("hgt", "60in"));
+        assert!(accepts("hgt", "190cm"));
+        assert!(!accepts("hgt", "190in"));
+        assert!(!accepts("hgt", "190"));
+        assert!(!accepts("hgt", "9"));
     }
 
     #[test]
     fn validates_hair_color() {
-        assert!(is_valid_hair_color("#123abc"));
-        assert!(!is_valid_hair_color("#123abz"));
-        assert!(!is_valid_hair_color("123abc"));
+        assert!(accepts("hcl", "#123abc"));
+        assert!(!accepts("hcl", "#123abz"));
+        assert!(!accepts("hcl", "123abc"));
     }
 
     #[test]
     fn validates_eye_color() {
-        assert!(is_valid_eye_color("brn"));
-        assert!(!is_valid_eye_color("wat"));
+        assert!(accepts("ecl", "brn"));
+        assert!(!accepts("ecl", "wat"));
     }
 
     #[test]
     fn validates_passport_id() {
-        assert!(is_valid_passport_id("000000001"));
-        assert!(!is_valid_passport_id("0123456789"));
+        assert!(accepts("pid", "000000001"));
+        assert!(!accepts("pid", "0123456789"));
     }
 
     #[test]
-    fn recognizes_invalid_passports() {
-        assert!(read_passports(
-            "\
+    fn validates_years() {
+        assert!(accepts("byr", "2002"));
+        assert!(!accepts("byr", "2003"));
+        assert!(accepts("iyr", "2010"));
+        assert!(!accepts("iyr", "2009"));
+        assert!(accepts("eyr", "2030"));
+        assert!(!accepts("eyr", "2031"));
+    }
+
+    #[test]
+    fn reads_custom_schema() {
+        let schema = "\
+# Visa
+vid required regex ^V[0-9]{4}$
+cls optional enum work study
+dur required units d 1 90 w 1 12"
+            .parse::<Schema>()
+            .expect("Failed to parse schema");
+        let visas =
+            read_passports("vid:V1234 dur:3w\n\nvid:V1234 cls:tourism dur:30d\n\nvid:V12 dur:91d");
+        assert_eq!(
+            visas.iter().map(|visa| schema.is_valid(visa)).collect_vec(),
+            vec![true, false, false]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_rules() {
+        assert_eq!(
+            "byr required between 1920 2002".parse::<Schema>().err(),
+            Some(SchemaError::UnknownRule("between".to_string()))
+        );
+        assert_eq!(
+            "byr sometimes".parse::<Schema>().err(),
+            Some(SchemaError::UnknownPresence("sometimes".to_string()))
+        );
+        assert_eq!(
+            "hgt required units cm 150".parse::<Schema>().err(),
+            Some(SchemaError::ParseError(ParseError::Missing("unit bounds")))
+        );
+    }
+
+    const INVALID: &str = "\
 eyr:1972 cid:100
 hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926
 
@@ -199,10 +942,13 @@ ecl:brn hgt:182cm pid:021572410 eyr:2020 byr:1992 cid:277
 
 hgt:59cm ecl:zzz
 eyr:2038 hcl:74454a iyr:2023
-pid:3556412378 byr:2007"
-        )
-        .iter()
-        .all(|passport| !passport.is_valid()));
+pid:3556412378 byr:2007";
+
+    #[test]
+    fn recognizes_invalid_passports() {
+        assert!(read_passports(INVALID)
+            .iter()
+            .all(|passport| !passport.is_valid()));
     }
 
     #[test]
@@ -226,4 +972,358 @@ iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719
         .iter()
         .all(|passport| passport.is_valid()));
     }
+
+    #[test]
+    fn reports_every_violation() {
+        let passport = Passport::read_lenient(
+            "hgt:59cm ecl:zzz\neyr:2038 hcl:74454a iyr:2023\npid:3556412378 byr:2007",
+        );
+        assert_eq!(
+            PASSPORT_SCHEMA
+                .validate(&passport)
+                .iter()
+                .map(Violation::to_string)
+                .collect_vec(),
+            vec![
+                "`byr` 2007 above max 2002",
+                "`iyr` 2023 above max 2020",
+                "`eyr` 2038 above max 2030",
+                "`hgt` 59cm below min 150cm",
+                "`hcl` `74454a` does not match #rrggbb",
+                "`ecl` `zzz` is not one of amb, blu, brn, gry, grn, hzl, oth",
+                "`pid` `3556412378` does not match nine digits",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_missing_fields_and_units() {
+        assert_eq!(
+            PASSPORT_SCHEMA
+                .validate(&Passport::read_lenient(
+                    "byr:1980 iyr:2012 eyr:2030 hgt:74 hcl:#623a2f pid:087499704"
+                ))
+                .iter()
+                .map(Violation::to_string)
+                .collect_vec(),
+            vec![
+                "`hgt` `74` has none of the units cm, in",
+                "missing field `ecl`"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_passport_positions() {
+        let reports = PASSPORT_SCHEMA.validate_all(INVALID);
+        assert_eq!(
+            reports
+                .iter()
+                .map(|report| (report.index, report.lines.clone()))
+                .collect_vec(),
+            vec![(0, 1..=2), (1, 4..=6), (2, 8..=9), (3, 11..=13)]
+        );
+        assert_eq!(
+            reports[2].to_string(),
+            "passport 2 (lines 8-9): `hcl` `dab227` does not match #rrggbb"
+        );
+    }
+
+    #[test]
+    fn reports_nothing_for_blank_input() {
+        assert_eq!(PASSPORT_SCHEMA.validate_all(""), vec![]);
+        assert_eq!(PASSPORT_SCHEMA.validate_all(" \n\n\n"), vec![]);
+    }
+
+    #[test]
+    fn summarizes_failures_per_field() {
+        let summary = summarize(&PASSPORT_SCHEMA.validate_all(INVALID));
+        assert_eq!(
+            summary.into_iter().collect_vec(),
+            vec![
+                ("byr".to_string(), 1),
+                ("ecl".to_string(), 1),
+                ("eyr".to_string(), 3),
+                ("hcl".to_string(), 2),
+                ("hgt".to_string(), 2),
+                ("iyr".to_string(), 1),
+                ("pid".to_string(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn solve_part2_from_reports() {
+        assert_eq!(
+            PASSPORT_SCHEMA
+                .validate_all(INPUT)
+                .iter()
+                .filter(|report| report.is_valid())
+                .count(),
+            179
+        );
+    }
+
+    #[test]
+    fn reads_passport_strictly() {
+        assert_eq!(
+            Passport::try_from("ecl:gry pid:860033327\ncid:147"),
+            Ok(Passport::read_lenient("ecl:gry pid:860033327\ncid:147"))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_records() {
+        assert_eq!(
+            Passport::try_from("ecl:gry pid860033327"),
+            Err(RecordError::MalformedField("pid860033327".to_string()))
+        );
+        assert_eq!(
+            Passport::try_from("ecl:gry :860033327"),
+            Err(RecordError::MalformedField(":860033327".to_string()))
+        );
+        assert_eq!(
+            Passport::try_from("ecl:gry ecl:brn"),
+            Err(RecordError::DuplicateField("ecl".to_string()))
+        );
+        assert_eq!(
+            Passport::try_from("ecl:gry vid:V1234"),
+            Err(RecordError::UnknownField("vid".to_string()))
+        );
+        assert_eq!(Passport::try_from(" \n"), Err(RecordError::Empty));
+    }
+
+    #[test]
+    fn reads_passport_leniently() {
+        let record = "ecl:gry pid860033327 ecl:brn vid:V1234";
+        assert_eq!(
+            PASSPORT_SCHEMA.read_passport(record, ParseMode::Lenient),
+            Ok(Passport::read_lenient(record))
+        );
+        assert_eq!(
+            PASSPORT_SCHEMA.read_passport("", ParseMode::Lenient),
+            Ok(Passport::read_lenient(""))
+        );
+    }
+
+    #[test]
+    fn reads_input_strictly() {
+        assert!(split_records(INPUT).all(|(_, record)| Passport::try_from(record).is_ok()));
+    }
+
+    #[test]
+    fn rejects_short_heights_without_panicking() {
+        assert!(!Passport::read_lenient("hgt:9").is_valid());
+        assert!(!Passport::read_lenient("hgt:").is_valid());
+    }
+
+    fn convert(passports: &[Passport], from: Format, to: Format) -> Vec<Passport> {
+        let written = write_passports_as(passports, from).expect("Failed to write passports");
+        let read = read_passports_as(&written, from).expect("Failed to read passports");
+        let written = write_passports_as(&read, to).expect("Failed to convert passports");
+        read_passports_as(&written, to).expect("Failed to read converted passports")
+    }
+
+    const FORMATS: [Format; 3] = [Format::Native, Format::Json, Format::Csv];
+
+    fn passport(fields: &[(&str, &str)]) -> Passport {
+        Passport(
+            fields
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn round_trips_between_formats() {
+        let passports = read_passports(EXAMPLE);
+        for (from, to) in FORMATS.iter().cartesian_product(&FORMATS) {
+            assert_eq!(convert(&passports, *from, *to), passports);
+        }
+        let cases = [
+            vec![],
+            vec![passport(&[("hcl", "a b")])],
+            vec![passport(&[("pid", "")])],
+            vec![passport(&[("cid", "1")]), passport(&[])],
+            vec![passport(&[("a:b", "1")])],
+        ];
+        for (passports, format) in cases.iter().cartesian_product(&FORMATS) {
+            match write_passports_as(passports, *format) {
+                Ok(written) => assert_eq!(
+                    read_passports_as(&written, *format).expect("Failed to read passports"),
+                    *passports,
+                    "{:?} as {:?}",
+                    passports,
+                    format
+                ),
+                Err(error) => assert!(
+                    matches!(
+                        error,
+                        ConversionError::UnrepresentableField { .. }
+                            | ConversionError::EmptyPassport { .. }
+                    ),
+                    "{}",
+                    error
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unrepresentable_passports() {
+        let unrepresentable = |passport: Passport, format| {
+            write_passports_as(&[passport], format).map_err(|error| error.to_string())
+        };
+        assert_eq!(
+            unrepresentable(passport(&[("hcl", "a b")]), Format::Native),
+            Err("Cannot write hcl:\"a b\" as Native".to_string())
+        );
+        assert_eq!(
+            unrepresentable(passport(&[("pid", "")]), Format::Csv),
+            Err("Cannot write pid:\"\" as Csv".to_string())
+        );
+        assert_eq!(
+            unrepresentable(passport(&[]), Format::Native),
+            Err("Cannot write passport 0 without fields as Native".to_string())
+        );
+        assert!(unrepresentable(passport(&[("pid", "")]), Format::Json).is_ok());
+    }
+
+    #[test]
+    fn reads_blank_native_input_as_no_passports() {
+        assert_eq!(read_passports(""), vec![]);
+        assert_eq!(read_passports(" \n\n\n"), vec![]);
+        assert_eq!(
+            write_passports_as(&[], Format::Native).ok(),
+            Some(String::new())
+        );
+    }
+
+    #[test]
+    fn reads_json_passports() {
+        let passports = read_passports_as(
+            r##"[{"hcl": "#cfa07d", "eyr": 2025, "pid": "166559648", "iyr": 2011, "ecl": "brn", "hgt": "59in"}]"##,
+            Format::Json,
+        )
+        .expect("Failed to read JSON");
+        assert_eq!(
+            passports,
+            read_passports("hcl:#cfa07d eyr:2025 pid:166559648\niyr:2011 ecl:brn hgt:59in")
+        );
+    }
+
+    #[test]
+    fn rejects_nested_json() {
+        assert!(matches!(
+            read_passports_as(r#"[{"hgt": {"cm": 150}}]"#, Format::Json),
+            Err(ConversionError::UnexpectedJson(_))
+        ));
+        assert!(matches!(
+            read_passports_as(r#"{"hgt": "150cm"}"#, Format::Json),
+            Err(ConversionError::UnexpectedJson(_))
+        ));
+    }
+
+    #[test]
+    fn writes_csv_with_empty_cells_for_missing_fields() {
+        assert_eq!(
+            write_passports_as(
+                &read_passports("byr:1937 hgt:183cm\n\ncid:147 byr:1929"),
+                Format::Csv
+            )
+            .expect("Failed to write CSV"),
+            "byr,cid,hgt\n1937,,183cm\n1929,147,\n"
+        );
+    }
+
+    #[test]
+    fn validates_identically_in_every_format() {
+        let passports = read_passports(INPUT);
+        for format in &[Format::Json, Format::Csv] {
+            let converted = read_passports_as(
+                &write_passports_as(&passports, *format).expect("Failed to write passports"),
+                *format,
+            )
+            .expect("Failed to read passports");
+            assert_eq!(part1(&converted), 204);
+            assert_eq!(part2(&converted), 179);
+        }
+    }
+
+    #[test]
+    fn parses_typed_fields() {
+        assert_eq!("74in".parse::<Height>(), Ok(Height::In(74)));
+        assert_eq!(
+            "165cm".parse::<Height>().map(|height| height.centimetres()),
+            Ok(165.0)
+        );
+        assert_eq!(
+            "#623a2f".parse::<Rgb>(),
+            Ok(Rgb {
+                red: 0x62,
+                green: 0x3a,
+                blue: 0x2f
+            })
+        );
+        assert_eq!("#623a2".parse::<Rgb>(), Err(ParseError::FormatError));
+        assert_eq!("hzl".parse::<EyeColor>(), Ok(EyeColor::Hazel));
+    }
+
+    #[test]
+    fn validates_into_typed_passport() {
+        let passport = ValidatedPassport::try_from(&Passport::read_lenient(
+            "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f",
+        ))
+        .expect("Failed to validate passport");
+        assert_eq!(passport.birth_year(), 1980);
+        assert_eq!(passport.height(), Height::In(74));
+        assert_eq!(passport.eye_color(), EyeColor::Green);
+        assert_eq!(passport.passport_id(), "087499704");
+        assert_eq!(passport.country_id(), None);
+    }
+
+    #[test]
+    fn refuses_to_type_invalid_passports() {
+        assert_eq!(
+            ValidatedPassport::try_from(&Passport::read_lenient(
+                "hcl:dab227 iyr:2012 ecl:brn hgt:182cm pid:021572410 eyr:2020 byr:1992 cid:277"
+            )),
+            Err(vec![Violation::NoMatch {
+                key: "hcl".to_string(),
+                value: "dab227".to_string(),
+                description: "#rrggbb".to_string()
+            }])
+        );
+    }
+
+    #[test]
+    fn queries_typed_passports() {
+        let passports = validated_passports(&read_passports(
+            "\
+pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980
+hcl:#623a2f
+
+eyr:2029 ecl:blu cid:129 byr:1989
+iyr:2014 pid:896056539 hcl:#a97842 hgt:165cm
+
+hcl:#888785
+hgt:164cm byr:2001 iyr:2015 cid:88
+pid:545766238 ecl:hzl
+eyr:2022",
+        ));
+        assert_eq!(
+            passports
+                .iter()
+                .filter(|passport| passport.height().centimetres() > 180.0)
+                .map(ValidatedPassport::passport_id)
+                .collect_vec(),
+            vec!["087499704"]
+        );
+    }
+
+    #[test]
+    fn solve_part2_with_typed_passports() {
+        assert_eq!(validated_passports(&read_passports(INPUT)).len(), 179);
+    }
 }
@@ -1,40 +1,250 @@
-use itertools::Itertools;
-use std::num::ParseIntError;
+use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
+use bit_vec::BitVec;
+use itertools::Itertools;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BoardingPassError {
+    #[error("Expected {expected} characters, found {found}")]
+    InvalidLength { expected: usize, found: usize },
+    #[error("Unexpected {character:?} at position {position}")]
+    InvalidCharacter { position: usize, character: char },
+    #[error("Seat at row {row}, column {column} is outside the aircraft")]
+    SeatOutOfRange { row: u32, column: u32 },
+    #[error("Seat at row {row}, column {column} is boarded twice")]
+    DuplicateSeat { row: u32, column: u32 },
+    #[error("Unsupported geometry of {row_bits} row bits and {column_bits} column bits")]
+    UnsupportedGeometry { row_bits: u32, column_bits: u32 },
+}
+
+/// The layout of an aircraft, given by the number of bits used to encode rows and columns.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Aircraft {
+    row_bits: u32,
+    column_bits: u32,
+}
+
+impl Aircraft {
+    /// 128 rows of 8 seats.
+    pub const STANDARD: Aircraft = Aircraft {
+        row_bits: 7,
+        column_bits: 3,
+    };
+
+    pub fn new(row_bits: u32, column_bits: u32) -> Result<Self, BoardingPassError> {
+        match row_bits.checked_add(column_bits) {
+            Some(bits) if bits < u32::BITS => Ok(Aircraft {
+                row_bits,
+                column_bits,
+            }),
+            _ => Err(BoardingPassError::UnsupportedGeometry {
+                row_bits,
+                column_bits,
+            }),
+        }
+    }
+
+    pub fn rows(&self) -> u32 {
+        1 << self.row_bits
+    }
+
+    pub fn columns(&self) -> u32 {
+        1 << self.column_bits
+    }
+
+    fn code_length(&self) -> usize {
+        (self.row_bits + self.column_bits) as usize
+    }
+
+    pub fn seat_id(&self, seat: &Seat) -> u32 {
+        seat.row << self.column_bits | seat.column
+    }
+
+    pub fn seat(&self, seat_id: u32) -> Seat {
+        Seat {
+            row: seat_id >> self.column_bits,
+            column: seat_id & (self.columns() - 1),
+        }
+    }
+
+    pub fn decode(&self, code: &str) -> Result<Seat, BoardingPassError> {
+        let found = code.chars().count();
+        if found != self.code_length() {
+            return Err(BoardingPassError::InvalidLength {
+                expected: self.code_length(),
+                found,
+            });
+        }
+        let mut seat = Seat { row: 0, column: 0 };
+        for (position, character) in code.chars().enumerate() {
+            let (part, bit) = match (position < self.row_bits as usize, character) {
+                (true, 'F') => (&mut seat.row, 0),
+                (true, 'B') => (&mut seat.row, 1),
+                (false, 'L') => (&mut seat.column, 0),
+                (false, 'R') => (&mut seat.column, 1),
+                _ => {
+                    return Err(BoardingPassError::InvalidCharacter {
+                        position,
+                        character,
+                    })
+                }
+            };
+            *part = *part << 1 | bit;
+        }
+        Ok(seat)
+    }
+
+    pub fn encode(&self, seat: &Seat) -> Result<String, BoardingPassError> {
+        if seat.row >= self.rows() || seat.column >= self.columns() {
+            return Err(BoardingPassError::SeatOutOfRange {
+                row: seat.row,
+                column: seat.column,
+            });
+        }
+        Ok(encode_binary('F', 'B', seat.row, self.row_bits)
+            + &encode_binary('L', 'R', seat.column, self.column_bits))
+    }
+}
+
+fn encode_binary(zero: char, one: char, value: u32, bits: u32) -> String {
+    (0..bits)
+        .rev()
+        .map(|bit| if value >> bit & 1 == 1 { one } else { zero })
+        .collect()
+}
+
 #[derive(PartialEq, Debug, Clone)]
-struct Seat {
-    row: u32,
-    column: u32,
+pub struct Seat {
+    pub row: u32,
+    pub column: u32,
 }
 
 impl Seat {
     fn seat_id(&self) -> u32 {
-        self.row * 8 + self.column
+        Aircraft::STANDARD.seat_id(self)
     }
 }
 
 impl FromStr for Seat {
-    type Err = ParseIntError;
+    type Err = BoardingPassError;
 
     fn from_str(code: &str) -> Result<Self, Self::Err> {
-        let (row, column) = code.split_at(7);
-        let row = decode_binary('F', 'B', row)?;
-        let column = decode_binary('L', 'R', column)?;
-        Ok(Seat { row, column })
+        Aircraft::STANDARD.decode(code)
+    }
+}
+
+/// Every seat of an aircraft, marked as occupied or empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeatMap {
+    aircraft: Aircraft,
+    occupied: BitVec,
+}
+
+/// Empty seats, split by whether they lie before the first, between or after the last occupied seat.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MissingSeats {
+    pub front: Vec<Seat>,
+    pub interior: Vec<Seat>,
+    pub back: Vec<Seat>,
+}
+
+impl SeatMap {
+    pub fn new(aircraft: Aircraft) -> Self {
+        SeatMap {
+            aircraft,
+            occupied: BitVec::from_elem((aircraft.rows() * aircraft.columns()) as usize, false),
+        }
+    }
+
+    /// Reads one boarding pass per line, rejecting passes for seats that are already occupied.
+    pub fn read(aircraft: Aircraft, input: &str) -> Result<Self, BoardingPassError> {
+        let mut seats = Self::new(aircraft);
+        for line in input.lines() {
+            seats.board(&aircraft.decode(line)?)?;
+        }
+        Ok(seats)
+    }
+
+    fn contains(&self, seat: &Seat) -> bool {
+        seat.row < self.aircraft.rows() && seat.column < self.aircraft.columns()
+    }
+
+    pub fn board(&mut self, seat: &Seat) -> Result<(), BoardingPassError> {
+        if !self.contains(seat) {
+            return Err(BoardingPassError::SeatOutOfRange {
+                row: seat.row,
+                column: seat.column,
+            });
+        }
+        let seat_id = self.aircraft.seat_id(seat) as usize;
+        if self.occupied[seat_id] {
+            return Err(BoardingPassError::DuplicateSeat {
+                row: seat.row,
+                column: seat.column,
+            });
+        }
+        self.occupied.set(seat_id, true);
+        Ok(())
+    }
+
+    /// Checks whether the seat is taken; seats outside the aircraft never are.
+    pub fn is_occupied(&self, seat: &Seat) -> bool {
+        self.contains(seat) && self.occupied[self.aircraft.seat_id(seat) as usize]
+    }
+
+    pub fn missing_seats(&self) -> MissingSeats {
+        let first = self.occupied.iter().position(|occupied| occupied);
+        let last = self.occupied.iter().rposition(|occupied| occupied);
+        let mut missing = MissingSeats::default();
+        for (seat_id, _) in self
+            .occupied
+            .iter()
+            .enumerate()
+            .filter(|(_, occupied)| !occupied)
+        {
+            let seat = self.aircraft.seat(seat_id as u32);
+            match (first, last) {
+                (Some(first), _) if seat_id < first => missing.front.push(seat),
+                (_, Some(last)) if seat_id < last => missing.interior.push(seat),
+                _ => missing.back.push(seat),
+            }
+        }
+        missing
     }
 }
 
-fn decode_binary(zero: char, one: char, binary_code: &str) -> Result<u32, ParseIntError> {
-    u32::from_str_radix(&binary_code.replace(zero, "0").replace(one, "1"), 2)
+impl Display for SeatMap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (row, seats) in self
+            .occupied
+            .iter()
+            .chunks(self.aircraft.columns() as usize)
+            .into_iter()
+            .enumerate()
+        {
+            if row > 0 {
+                writeln!(f)?;
+            }
+            for occupied in seats {
+                write!(f, "{}", if occupied { '#' } else { '.' })?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[aoc_generator(day5)]
-fn read_seat_ids(input: &str) -> Result<Vec<u32>, ParseIntError> {
+fn read_seat_ids(input: &str) -> Result<Vec<u32>, BoardingPassError> {
+    let mut seats = SeatMap::new(Aircraft::STANDARD);
     input
         .lines()
-        .map(|line| line.parse::<Seat>())
-        .map_results(|seat| seat.seat_id())
+        .map(|line| {
+            let seat = line.parse::<Seat>()?;
+            seats.board(&seat)?;
+            Ok(seat.seat_id())
+        })
         .collect()
 }
 
@@ -76,10 +286,11 @@ mod tests {
 
     #[test]
     fn decodes_examples() {
-        let seats: Result<Vec<Seat>, ParseIntError> = ["BFFFBBFRRR", "FFFBBBFRRR", "BBFFBBFRLL"]
-            .iter()
-            .map(|code| code.parse())
-            .collect();
+        let seats: Result<Vec<Seat>, BoardingPassError> =
+            ["BFFFBBFRRR", "FFFBBBFRRR", "BBFFBBFRLL"]
+                .iter()
+                .map(|code| code.parse())
+                .collect();
 
         assert_eq!(seats, Ok(EXAMPLE_SEATS.to_vec()));
     }
@@ -104,4 +315,148 @@ mod tests {
             Ok(Some(714))
         );
     }
+
+    #[test]
+    fn encodes_examples() {
+        assert_eq!(
+            EXAMPLE_SEATS
+                .iter()
+                .map(|seat| Aircraft::STANDARD.encode(seat))
+                .collect::<Result<Vec<_>, _>>(),
+            Ok(vec![
+                "BFFFBBFRRR".to_string(),
+                "FFFBBBFRRR".to_string(),
+                "BBFFBBFRLL".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn round_trips_every_seat_of_a_small_aircraft() {
+        let aircraft = Aircraft::new(3, 2).expect("Failed to create aircraft");
+        for row in 0..aircraft.rows() {
+            for column in 0..aircraft.columns() {
+                let seat = Seat { row, column };
+                let code = aircraft.encode(&seat).expect("Failed to encode seat");
+                assert_eq!(code.len(), 5);
+                assert_eq!(aircraft.decode(&code), Ok(seat));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_codes() {
+        assert_eq!(
+            "BFFFBBFRR".parse::<Seat>(),
+            Err(BoardingPassError::InvalidLength {
+                expected: 10,
+                found: 9
+            })
+        );
+        assert_eq!(
+            "BFFFBBFRRX".parse::<Seat>(),
+            Err(BoardingPassError::InvalidCharacter {
+                position: 9,
+                character: 'X'
+            })
+        );
+        assert_eq!(
+            "BFFFBBRRRR".parse::<Seat>(),
+            Err(BoardingPassError::InvalidCharacter {
+                position: 6,
+                character: 'R'
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_seats_outside_the_aircraft() {
+        assert_eq!(
+            Aircraft::STANDARD.encode(&Seat {
+                row: 128,
+                column: 0
+            }),
+            Err(BoardingPassError::SeatOutOfRange {
+                row: 128,
+                column: 0
+            })
+        );
+        assert!(Aircraft::new(30, 3).is_err());
+        assert_eq!(
+            Aircraft::new(u32::MAX, 1),
+            Err(BoardingPassError::UnsupportedGeometry {
+                row_bits: u32::MAX,
+                column_bits: 1
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_boarding_passes() {
+        assert_eq!(
+            read_seat_ids("BFFFBBFRRR\nFFFBBBFRRR\nBFFFBBFRRR"),
+            Err(BoardingPassError::DuplicateSeat { row: 70, column: 7 })
+        );
+        assert_eq!(
+            SeatMap::read(Aircraft::STANDARD, "BFFFBBFRRR\nBFFFBBFRRR"),
+            Err(BoardingPassError::DuplicateSeat { row: 70, column: 7 })
+        );
+    }
+
+    const SMALL_CABIN: &str = "\
+FBLR
+FBRL
+FBRR
+BFLL
+BFRL
+BFRR
+BBLL";
+
+    #[test]
+    fn renders_cabin() {
+        let aircraft = Aircraft::new(2, 2).expect("Failed to create aircraft");
+        let seats = SeatMap::read(aircraft, SMALL_CABIN).expect("Failed to read seats");
+        assert!(seats.is_occupied(&Seat { row: 1, column: 1 }));
+        assert!(!seats.is_occupied(&Seat { row: 2, column: 1 }));
+        assert_eq!(seats.to_string(), "....\n.###\n#.##\n#...");
+    }
+
+    #[test]
+    fn does_not_alias_columns_into_the_next_row() {
+        let aircraft = Aircraft::new(2, 2).expect("Failed to create aircraft");
+        let seats = SeatMap::read(aircraft, "FBLR").expect("Failed to read seats");
+        assert!(seats.is_occupied(&Seat { row: 1, column: 1 }));
+        assert!(!seats.is_occupied(&Seat { row: 0, column: 5 }));
+    }
+
+    #[test]
+    fn lists_missing_seats() {
+        let aircraft = Aircraft::new(2, 2).expect("Failed to create aircraft");
+        let seats = SeatMap::read(aircraft, SMALL_CABIN).expect("Failed to read seats");
+        let missing = seats.missing_seats();
+        assert_eq!(missing.front.len(), 5);
+        assert_eq!(missing.interior, vec![Seat { row: 2, column: 1 }]);
+        assert_eq!(
+            missing.back,
+            vec![
+                Seat { row: 3, column: 1 },
+                Seat { row: 3, column: 2 },
+                Seat { row: 3, column: 3 }
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_own_seat_as_only_interior_gap() {
+        let seats = SeatMap::read(Aircraft::STANDARD, INPUT).expect("Failed to read seats");
+        assert_eq!(
+            seats
+                .missing_seats()
+                .interior
+                .iter()
+                .map(Seat::seat_id)
+                .collect_vec(),
+            vec![714]
+        );
+    }
 }
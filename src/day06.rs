@@ -1,32 +1,272 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
 use itertools::Itertools;
-use std::collections::HashSet;
-use std::iter::FromIterator;
-
-fn count_questions_with_any_yes(group: &str) -> usize {
-    group
-        .lines()
-        .flat_map(|answers| answers.chars())
-        .unique()
-        .count()
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CustomsError {
+    #[error("Invalid answer: {0:?}")]
+    InvalidAnswer(char),
+    #[error("Group without people")]
+    EmptyGroup,
+}
+
+/// The questions answered with "yes", one bit per question from `a` to `z`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Answers(u32);
+
+impl Answers {
+    pub const NONE: Answers = Answers(0);
+    pub const ALL: Answers = Answers((1 << 26) - 1);
+
+    pub fn contains(&self, question: char) -> bool {
+        question.is_ascii_lowercase() && self.0 & Self::bit(question) != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn union(&self, other: &Answers) -> Answers {
+        Answers(self.0 | other.0)
+    }
+
+    pub fn intersection(&self, other: &Answers) -> Answers {
+        Answers(self.0 & other.0)
+    }
+
+    pub fn symmetric_difference(&self, other: &Answers) -> Answers {
+        Answers(self.0 ^ other.0)
+    }
+
+    pub fn questions(&self) -> impl Iterator<Item = char> + '_ {
+        ('a'..='z').filter(move |question| self.contains(*question))
+    }
+
+    fn bit(question: char) -> u32 {
+        1 << (question as u32 - 'a' as u32)
+    }
 }
 
-fn count_agreements(group: &str) -> usize {
-    group
-        .lines()
-        .map(|answers| HashSet::from_iter(answers.chars()))
-        .fold1(|a, b| a.intersection(&b).cloned().collect())
-        .unwrap_or_else(HashSet::new)
-        .len()
+impl FromStr for Answers {
+    type Err = CustomsError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        input.chars().try_fold(Answers::NONE, |answers, question| {
+            if question.is_ascii_lowercase() {
+                Ok(Answers(answers.0 | Self::bit(question)))
+            } else {
+                Err(CustomsError::InvalidAnswer(question))
+            }
+        })
+    }
+}
+
+/// The answers of each person in a group, one person per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group(Vec<Answers>);
+
+impl Group {
+    pub fn people(&self) -> &[Answers] {
+        &self.0
+    }
+
+    /// Questions anyone in the group answered with "yes".
+    pub fn anyone(&self) -> Answers {
+        self.0
+            .iter()
+            .fold(Answers::NONE, |union, answers| union.union(answers))
+    }
+
+    /// Questions everyone in the group answered with "yes".
+    pub fn everyone(&self) -> Answers {
+        self.0.iter().fold(Answers::ALL, |intersection, answers| {
+            intersection.intersection(answers)
+        })
+    }
+
+    /// Questions answered with "yes" by at least `count` people of the group.
+    pub fn at_least(&self, count: usize) -> Answers {
+        ('a'..='z')
+            .filter(|question| {
+                self.0
+                    .iter()
+                    .filter(|answers| answers.contains(*question))
+                    .count()
+                    >= count
+            })
+            .fold(Answers::NONE, |answers, question| {
+                Answers(answers.0 | Answers::bit(question))
+            })
+    }
+}
+
+impl FromStr for Group {
+    type Err = CustomsError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let people = input
+            .lines()
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if people.is_empty() {
+            return Err(CustomsError::EmptyGroup);
+        }
+        Ok(Group(people))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuestionStatistics {
+    pub question: char,
+    /// Groups in which anyone answered the question.
+    pub groups_answered: usize,
+    /// Groups in which everyone answered the question.
+    pub groups_unanimous: usize,
+    /// People who answered the question.
+    pub individuals: usize,
+    /// Share of all people who answered the question.
+    pub frequency: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupStatistics {
+    pub index: usize,
+    pub size: usize,
+    pub anyone: usize,
+    pub everyone: usize,
+    /// Share of the questions answered by anyone that everyone answered, 1 if nobody answered any.
+    pub agreement: f64,
+}
+
+/// Statistics per question and per group of a survey.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurveyReport {
+    pub people: usize,
+    pub questions: Vec<QuestionStatistics>,
+    pub groups: Vec<GroupStatistics>,
+}
+
+impl SurveyReport {
+    /// Collects the statistics, listing only questions answered by anyone.
+    pub fn new(groups: &[Group]) -> Self {
+        let people = groups.iter().map(|group| group.people().len()).sum();
+        let questions = ('a'..='z')
+            .map(|question| {
+                let individuals = groups
+                    .iter()
+                    .flat_map(|group| group.people())
+                    .filter(|answers| answers.contains(question))
+                    .count();
+                QuestionStatistics {
+                    question,
+                    groups_answered: groups
+                        .iter()
+                        .filter(|group| group.anyone().contains(question))
+                        .count(),
+                    groups_unanimous: groups
+                        .iter()
+                        .filter(|group| group.everyone().contains(question))
+                        .count(),
+                    individuals,
+                    frequency: individuals as f64 / people as f64,
+                }
+            })
+            .filter(|statistics| statistics.individuals > 0)
+            .collect();
+        let groups = groups
+            .iter()
+            .enumerate()
+            .map(|(index, group)| {
+                let anyone = group.anyone().len();
+                let everyone = group.everyone().len();
+                GroupStatistics {
+                    index,
+                    size: group.people().len(),
+                    anyone,
+                    everyone,
+                    agreement: if anyone == 0 {
+                        1.0
+                    } else {
+                        everyone as f64 / anyone as f64
+                    },
+                }
+            })
+            .collect();
+        SurveyReport {
+            people,
+            questions,
+            groups,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "people": self.people,
+            "questions": self.questions.iter().map(|question| json!({
+                "question": question.question.to_string(),
+                "groups_answered": question.groups_answered,
+                "groups_unanimous": question.groups_unanimous,
+                "individuals": question.individuals,
+                "frequency": question.frequency,
+            })).collect_vec(),
+            "groups": self.groups.iter().map(|group| json!({
+                "index": group.index,
+                "size": group.size,
+                "anyone": group.anyone,
+                "everyone": group.everyone,
+                "agreement": group.agreement,
+            })).collect_vec(),
+        })
+    }
+}
+
+impl Display for SurveyReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "question  groups  unanimous  individuals  frequency")?;
+        for question in &self.questions {
+            writeln!(
+                f,
+                "{:<8}  {:>6}  {:>9}  {:>11}  {:>9.3}",
+                question.question,
+                question.groups_answered,
+                question.groups_unanimous,
+                question.individuals,
+                question.frequency
+            )?;
+        }
+        writeln!(f)?;
+        write!(f, "group  size  anyone  everyone  agreement")?;
+        for group in &self.groups {
+            write!(
+                f,
+                "\n{:<5}  {:>4}  {:>6}  {:>8}  {:>9.3}",
+                group.index, group.size, group.anyone, group.everyone, group.agreement
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[aoc_generator(day6)]
+fn read_groups(input: &str) -> Result<Vec<Group>, CustomsError> {
+    input.split("\n\n").map(str::parse).collect()
 }
 
 #[aoc(day6, part1)]
-fn part1(input: &str) -> usize {
-    input.split("\n\n").map(count_questions_with_any_yes).sum()
+fn part1(groups: &[Group]) -> usize {
+    groups.iter().map(|group| group.anyone().len()).sum()
 }
 
 #[aoc(day6, part2)]
-fn part2(input: &str) -> usize {
-    input.split("\n\n").map(count_agreements).sum()
+fn part2(groups: &[Group]) -> usize {
+    groups.iter().map(|group| group.everyone().len()).sum()
 }
 
 #[cfg(test)]
@@ -50,15 +290,25 @@ a
 
 b";
 
+    fn group(input: &str) -> Group {
+        input.parse().expect("Failed to parse group")
+    }
+
+    fn solve(solver: fn(&[Group]) -> usize, input: &str) -> Result<usize, CustomsError> {
+        read_groups(input).map(|groups| solver(&groups))
+    }
+
     #[test]
     fn counts_any_yes() {
         assert_eq!(
-            count_questions_with_any_yes(
+            group(
                 "\
 abcx
 abcy
 abcz"
-            ),
+            )
+            .anyone()
+            .len(),
             6
         );
     }
@@ -68,31 +318,155 @@ abcz"
         assert_eq!(
             ["abc", "a\nb\nc", "ab\nac", "a\na\na\na", "b"]
                 .iter()
-                .map(|group| count_agreements(group))
+                .map(|input| group(input).everyone().len())
                 .collect_vec(),
             vec![3, 0, 1, 1, 1]
         );
     }
 
+    #[test]
+    fn combines_answers() {
+        let left = "abc".parse::<Answers>().expect("Failed to parse answers");
+        let right = "bcd".parse::<Answers>().expect("Failed to parse answers");
+        assert_eq!(left.union(&right).questions().collect::<String>(), "abcd");
+        assert_eq!(
+            left.intersection(&right).questions().collect::<String>(),
+            "bc"
+        );
+        assert_eq!(
+            left.symmetric_difference(&right)
+                .questions()
+                .collect::<String>(),
+            "ad"
+        );
+    }
+
+    #[test]
+    fn counts_questions_answered_by_at_least_k_people() {
+        let group = group("ab\nac\nad\nb");
+        assert_eq!(group.at_least(0), Answers::ALL);
+        assert_eq!(group.at_least(1), group.anyone());
+        assert_eq!(group.at_least(2).questions().collect::<String>(), "ab");
+        assert_eq!(group.at_least(3).questions().collect::<String>(), "a");
+        assert_eq!(group.at_least(4), group.everyone());
+    }
+
+    #[test]
+    fn rejects_invalid_answers() {
+        assert_eq!(
+            "abC".parse::<Answers>(),
+            Err(CustomsError::InvalidAnswer('C'))
+        );
+    }
+
+    #[test]
+    fn rejects_empty_groups() {
+        assert_eq!("".parse::<Group>(), Err(CustomsError::EmptyGroup));
+        assert_eq!(read_groups("a\n\n\n\nb"), Err(CustomsError::EmptyGroup));
+    }
+
     const INPUT: &str = include_str!("../input/2020/day6.txt");
 
     #[test]
     fn solve_example_part1() {
-        assert_eq!(part1(EXAMPLE), 11);
+        assert_eq!(solve(part1, EXAMPLE), Ok(11));
     }
 
     #[test]
     fn solve_part1() {
-        assert_eq!(part1(INPUT), 6590);
+        assert_eq!(solve(part1, INPUT), Ok(6590));
     }
 
     #[test]
     fn solve_example_part2() {
-        assert_eq!(part2(EXAMPLE), 6);
+        assert_eq!(solve(part2, EXAMPLE), Ok(6));
     }
 
     #[test]
     fn solve_part2() {
-        assert_eq!(part2(INPUT), 3288);
+        assert_eq!(solve(part2, INPUT), Ok(3288));
+    }
+
+    fn example_report() -> SurveyReport {
+        SurveyReport::new(&read_groups(EXAMPLE).expect("Failed to read example"))
+    }
+
+    #[test]
+    fn reports_question_statistics() {
+        assert_eq!(
+            example_report()
+                .questions
+                .iter()
+                .map(|question| (
+                    question.question,
+                    question.groups_answered,
+                    question.groups_unanimous,
+                    question.individuals
+                ))
+                .collect_vec(),
+            vec![('a', 4, 3, 8), ('b', 4, 2, 4), ('c', 3, 1, 3)]
+        );
+    }
+
+    #[test]
+    fn reports_group_statistics() {
+        assert_eq!(
+            example_report()
+                .groups
+                .iter()
+                .map(|group| (group.size, group.anyone, group.everyone))
+                .collect_vec(),
+            vec![(1, 3, 3), (3, 3, 0), (2, 3, 1), (4, 1, 1), (1, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn sums_up_to_puzzle_answers() {
+        let report = SurveyReport::new(&read_groups(INPUT).expect("Failed to read input"));
+        assert_eq!(
+            report
+                .questions
+                .iter()
+                .map(|question| question.groups_answered)
+                .sum::<usize>(),
+            6590
+        );
+        assert_eq!(
+            report
+                .questions
+                .iter()
+                .map(|question| question.groups_unanimous)
+                .sum::<usize>(),
+            3288
+        );
+    }
+
+    #[test]
+    fn renders_report_as_table() {
+        assert_eq!(
+            example_report().to_string(),
+            "\
+question  groups  unanimous  individuals  frequency
+a              4          3            8      0.727
+b              4          2            4      0.364
+c              3          1            3      0.273
+
+group  size  anyone  everyone  agreement
+0         1       3         3      1.000
+1         3       3         0      0.000
+2         2       3         1      0.333
+3         4       1         1      1.000
+4         1       1         1      1.000"
+        );
+    }
+
+    #[test]
+    fn renders_report_as_json() {
+        let json = example_report().to_json();
+        assert_eq!(json["people"], 11);
+        assert_eq!(json["questions"][1]["question"], "b");
+        assert_eq!(json["questions"][1]["groups_unanimous"], 2);
+        assert_eq!(json["groups"][2]["size"], 2);
+        assert_eq!(json["groups"][1]["agreement"], 0.0);
     }
 }
@@ -1,39 +1,38 @@
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
 use std::str::FromStr;
 
 use itertools::Itertools;
+use thiserror::Error;
 
-use crate::{read_lines, ParseError};
+use crate::ParseError;
 
-type Bag = String;
-
-struct Rule {
-    container: Bag,
-    contents: Vec<(usize, Bag)>,
+/// A rule borrowing the bag names from the line it was read from.
+struct Rule<'a> {
+    container: &'a str,
+    contents: Vec<(usize, &'a str)>,
 }
 
-impl FromStr for Rule {
-    type Err = ParseError;
-
-    fn from_str(line: &str) -> Result<Self, Self::Err> {
+impl<'a> Rule<'a> {
+    fn parse(line: &'a str) -> Result<Self, ParseError> {
         let (container, contents) = line
             .splitn(2, " bags contain ")
             .collect_tuple()
             .ok_or(ParseError::FormatError)?;
-        let contents = read_counts(contents)?;
         Ok(Rule {
-            container: container.to_string(),
-            contents,
+            container: read_bag(container)?,
+            contents: read_counts(contents)?,
         })
     }
 }
 
-fn read_counts(input: &str) -> Result<Vec<(usize, String)>, ParseError> {
+fn read_counts(input: &str) -> Result<Vec<(usize, &str)>, ParseError> {
     Ok(if input == "no other bags." {
         vec![]
     } else {
         input
-            .strip_suffix(".")
+            .strip_suffix('.')
             .ok_or(ParseError::Missing("dot at end of rule"))?
             .split(", ")
             .map(read_count)
@@ -41,110 +40,730 @@ fn read_counts(input: &str) -> Result<Vec<(usize, String)>, ParseError> {
     })
 }
 
-fn read_count(input: &str) -> Result<(usize, String), ParseError> {
-    let parts = input.split_whitespace().collect_vec();
-    if parts.len() != 4 || !matches!(parts[3], "bags" | "bag") {
-        Err(ParseError::FormatError)
-    } else {
-        Ok((parts[0].parse()?, format!("{} {}", parts[1], parts[2])))
+fn read_count(input: &str) -> Result<(usize, &str), ParseError> {
+    let (count, bag) = input
+        .splitn(2, ' ')
+        .collect_tuple()
+        .ok_or(ParseError::FormatError)?;
+    let bag = bag
+        .strip_suffix(" bags")
+        .or_else(|| bag.strip_suffix(" bag"))
+        .ok_or(ParseError::FormatError)?;
+    Ok((count.parse()?, read_bag(bag)?))
+}
+
+/// Accepts bag names of exactly two words, like "shiny gold".
+fn read_bag(name: &str) -> Result<&str, ParseError> {
+    match name.split(' ').collect_tuple() {
+        Some((adjective, color)) if !adjective.is_empty() && !color.is_empty() => Ok(name),
+        _ => Err(ParseError::FormatError),
     }
 }
 
-#[aoc_generator(day7)]
-fn read_rules(input: &str) -> Result<Vec<Rule>, ParseError> {
-    read_lines(input)
+fn read_rules(input: &str) -> Result<Vec<Rule<'_>>, ParseError> {
+    input.lines().map(Rule::parse).collect()
 }
 
-#[aoc(day7, part1, Rules)]
-fn part1_rules(rules: &[Rule]) -> usize {
-    let mut bags: HashSet<String> = HashSet::new();
-    let mut queue: VecDeque<String> = VecDeque::new();
-    queue.push_back("shiny gold".to_string());
-    while let Some(bag) = queue.pop_front() {
-        let containers = rules
+impl fmt::Display for Rule<'_> {
+    /// Writes the rule in the puzzle grammar, like "bright white bags contain 1 shiny gold bag."
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bags contain ", self.container)?;
+        if self.contents.is_empty() {
+            return write!(f, "no other bags.");
+        }
+        for (index, (count, bag)) in self.contents.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            let noun = if *count == 1 { "bag" } else { "bags" };
+            write!(f, "{} {} {}", count, bag, noun)?;
+        }
+        write!(f, ".")
+    }
+}
+
+/// A rule owning its bag names, as kept in a [`RuleSet`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BagRule {
+    container: String,
+    contents: Vec<(usize, String)>,
+}
+
+impl BagRule {
+    pub fn container(&self) -> &str {
+        &self.container
+    }
+
+    /// The bags inside the container, with their counts, in the order of the rule.
+    pub fn contents(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.contents
             .iter()
-            .filter(|rule| rule.contents.iter().any(|(_, content)| bag == *content))
-            .map(|rule| rule.container.clone())
-            .collect_vec();
-        queue.extend(containers.iter().cloned());
-        bags.extend(containers.iter().cloned());
+            .map(|(count, bag)| (*count, bag.as_str()))
+    }
+
+    fn mentions(&self, name: &str) -> bool {
+        self.container == name || self.contents.iter().any(|(_, bag)| bag == name)
+    }
+
+    fn as_rule(&self) -> Rule<'_> {
+        Rule {
+            container: &self.container,
+            contents: self.contents().collect(),
+        }
     }
-    bags.len()
 }
 
-#[aoc(day7, part1, Map)]
-fn part1(rules: &[Rule]) -> usize {
-    let contained_in: HashMap<Bag, Vec<Bag>> = rules
-        .iter()
-        .flat_map(|rule| {
-            rule.contents
+impl From<&Rule<'_>> for BagRule {
+    fn from(rule: &Rule) -> Self {
+        BagRule {
+            container: rule.container.to_string(),
+            contents: rule
+                .contents
+                .iter()
+                .map(|(count, bag)| (*count, bag.to_string()))
+                .collect(),
+        }
+    }
+}
+
+/// An editable list of bag rules, kept in their original order so that they can be written back.
+///
+/// Edits only keep the rules well formed; cycles are reported when building a [`BagGraph`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleSet {
+    rules: Vec<BagRule>,
+}
+
+impl RuleSet {
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn rules(&self) -> &[BagRule] {
+        &self.rules
+    }
+
+    pub fn rule(&self, container: &str) -> Option<&BagRule> {
+        self.rules.iter().find(|rule| rule.container == container)
+    }
+
+    fn rule_mut(&mut self, container: &str) -> Result<&mut BagRule, GraphError> {
+        self.rules
+            .iter_mut()
+            .find(|rule| rule.container == container)
+            .ok_or_else(|| GraphError::UnknownBag(container.to_string()))
+    }
+
+    fn mentions(&self, name: &str) -> bool {
+        self.rules.iter().any(|rule| rule.mentions(name))
+    }
+
+    /// Adds an empty bag at the end of the rules.
+    pub fn add_bag(&mut self, name: &str) -> Result<(), GraphError> {
+        read_bag(name)?;
+        if self.rule(name).is_some() {
+            return Err(GraphError::DuplicateBag(name.to_string()));
+        }
+        self.rules.push(BagRule {
+            container: name.to_string(),
+            contents: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Removes the rule for the bag along with every mention of it inside other bags.
+    pub fn remove_bag(&mut self, name: &str) -> Result<(), GraphError> {
+        if !self.mentions(name) {
+            return Err(GraphError::UnknownBag(name.to_string()));
+        }
+        self.rules.retain(|rule| rule.container != name);
+        for rule in &mut self.rules {
+            rule.contents.retain(|(_, bag)| bag != name);
+        }
+        Ok(())
+    }
+
+    /// Sets how many of the content bags the container holds, adding the content at the end
+    /// if it is new and removing it for a count of zero.
+    pub fn set_count(
+        &mut self,
+        container: &str,
+        content: &str,
+        count: usize,
+    ) -> Result<(), GraphError> {
+        read_bag(content)?;
+        let rule = self.rule_mut(container)?;
+        match rule.contents.iter().position(|(_, bag)| bag == content) {
+            Some(index) if count == 0 => {
+                rule.contents.remove(index);
+            }
+            Some(index) => rule.contents[index].0 = count,
+            None if count == 0 => {}
+            None => rule.contents.push((count, content.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Renames the bag in its own rule and wherever it is inside other bags.
+    pub fn rename(&mut self, name: &str, new_name: &str) -> Result<(), GraphError> {
+        read_bag(new_name)?;
+        if !self.mentions(name) {
+            return Err(GraphError::UnknownBag(name.to_string()));
+        }
+        if self.mentions(new_name) {
+            return Err(GraphError::DuplicateBag(new_name.to_string()));
+        }
+        for rule in &mut self.rules {
+            if rule.container == name {
+                rule.container = new_name.to_string();
+            }
+            for (_, bag) in &mut rule.contents {
+                if bag == name {
+                    *bag = new_name.to_string();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for RuleSet {
+    type Err = GraphError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let rules = read_rules(input)?;
+        let mut rule_set = RuleSet::default();
+        for rule in &rules {
+            if rule_set.rule(rule.container).is_some() {
+                return Err(GraphError::DuplicateBag(rule.container.to_string()));
+            }
+            rule_set.rules.push(BagRule::from(rule));
+        }
+        Ok(rule_set)
+    }
+}
+
+impl fmt::Display for RuleSet {
+    /// Writes one rule per line, each line ending with a newline like the puzzle input.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for rule in &self.rules {
+            writeln!(f, "{}", rule.as_rule())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum GraphError {
+    #[error(transparent)]
+    ParseError(#[from] ParseError),
+    #[error("Bags contain themselves: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    #[error("Too many bags inside {0}")]
+    Overflow(String),
+    #[error("Unknown bag: {0}")]
+    UnknownBag(String),
+    #[error("Bag already exists: {0}")]
+    DuplicateBag(String),
+}
+
+/// Index of a bag in a [`BagGraph`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BagId(usize);
+
+/// The bag rules as a graph of interned bag names, with edges from containers to contents
+/// labelled by the number of contained bags.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BagGraph {
+    names: Vec<String>,
+    ids: HashMap<String, BagId>,
+    contents: Vec<Vec<(BagId, usize)>>,
+    containers: Vec<Vec<(BagId, usize)>>,
+    /// All bags, each one listed after all bags inside it.
+    order: Vec<BagId>,
+}
+
+impl BagGraph {
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn id(&self, name: &str) -> Option<BagId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, bag: BagId) -> &str {
+        &self.names[bag.0]
+    }
+
+    pub fn bags(&self) -> impl Iterator<Item = BagId> {
+        (0..self.len()).map(BagId)
+    }
+
+    /// The bags directly inside the given bag, with their counts.
+    pub fn contents(&self, bag: BagId) -> &[(BagId, usize)] {
+        &self.contents[bag.0]
+    }
+
+    /// The bags directly containing the given bag, with the number of times they contain it.
+    pub fn containers(&self, bag: BagId) -> &[(BagId, usize)] {
+        &self.containers[bag.0]
+    }
+
+    pub fn bag(&self, name: &str) -> Result<BagId, GraphError> {
+        self.id(name)
+            .ok_or_else(|| GraphError::UnknownBag(name.to_string()))
+    }
+
+    /// Marks the bags reachable from the given bag along the given edges, including the bag itself.
+    fn reachable(&self, bag: BagId, edges: fn(&Self, BagId) -> &[(BagId, usize)]) -> Vec<bool> {
+        let mut reachable = vec![false; self.len()];
+        let mut stack = vec![bag];
+        while let Some(bag) = stack.pop() {
+            if !std::mem::replace(&mut reachable[bag.0], true) {
+                stack.extend(edges(self, bag).iter().map(|(next, _)| *next));
+            }
+        }
+        reachable
+    }
+
+    /// Counts all bags inside the given bag, handling each bag inside only once.
+    pub fn count_contents(&self, bag: BagId) -> Result<usize, GraphError> {
+        let reachable = self.reachable(bag, Self::contents);
+        let mut totals = vec![0usize; self.len()];
+        for &container in self.order.iter().filter(|bag| reachable[bag.0]) {
+            totals[container.0] = self
+                .contents(container)
+                .iter()
+                .try_fold(0usize, |total, (content, count)| {
+                    totals[content.0]
+                        .checked_add(1)
+                        .and_then(|bags| bags.checked_mul(*count))
+                        .and_then(|bags| total.checked_add(bags))
+                })
+                .ok_or_else(|| GraphError::Overflow(self.name(container).to_string()))?;
+        }
+        Ok(totals[bag.0])
+    }
+
+    /// All bags that directly or indirectly contain the given bag, ordered by name.
+    pub fn ancestors(&self, name: &str) -> Result<Vec<&str>, GraphError> {
+        let bag = self.bag(name)?;
+        let reachable = self.reachable(bag, Self::containers);
+        Ok(self
+            .bags()
+            .filter(|ancestor| reachable[ancestor.0] && *ancestor != bag)
+            .map(|ancestor| self.name(ancestor))
+            .sorted()
+            .collect())
+    }
+
+    /// All bags inside the given bag, with the total number of times each one occurs.
+    pub fn descendants(&self, name: &str) -> Result<BTreeMap<&str, usize>, GraphError> {
+        let bag = self.bag(name)?;
+        let reachable = self.reachable(bag, Self::contents);
+        let mut multiplicities = vec![0usize; self.len()];
+        multiplicities[bag.0] = 1;
+        for &container in self.order.iter().rev().filter(|bag| reachable[bag.0]) {
+            for (content, count) in self.contents(container) {
+                multiplicities[content.0] = multiplicities[container.0]
+                    .checked_mul(*count)
+                    .and_then(|bags| bags.checked_add(multiplicities[content.0]))
+                    .ok_or_else(|| GraphError::Overflow(self.name(container).to_string()))?;
+            }
+        }
+        Ok(self
+            .bags()
+            .filter(|descendant| reachable[descendant.0] && *descendant != bag)
+            .map(|descendant| (self.name(descendant), multiplicities[descendant.0]))
+            .collect())
+    }
+
+    /// Every chain of bags leading from the outer bag down to the inner bag, both included.
+    pub fn paths(&self, outer: &str, inner: &str) -> Result<Vec<Vec<&str>>, GraphError> {
+        let outer = self.bag(outer)?;
+        let inner = self.bag(inner)?;
+        let leads_to_inner = self.reachable(inner, Self::containers);
+        let mut paths = Vec::new();
+        if leads_to_inner[outer.0] {
+            self.collect_paths(&mut vec![outer], inner, &leads_to_inner, &mut paths);
+        }
+        Ok(paths)
+    }
+
+    fn collect_paths<'a>(
+        &'a self,
+        path: &mut Vec<BagId>,
+        inner: BagId,
+        leads_to_inner: &[bool],
+        paths: &mut Vec<Vec<&'a str>>,
+    ) {
+        let bag = *path.last().unwrap();
+        if bag == inner {
+            paths.push(path.iter().map(|bag| self.name(*bag)).collect());
+            return;
+        }
+        for (content, _) in self.contents(bag) {
+            if leads_to_inner[content.0] {
+                path.push(*content);
+                self.collect_paths(path, inner, leads_to_inner, paths);
+                path.pop();
+            }
+        }
+    }
+
+    /// The number of levels of bags nested inside the given bag.
+    pub fn depth(&self, name: &str) -> Result<usize, GraphError> {
+        let bag = self.bag(name)?;
+        let mut depths = vec![0; self.len()];
+        for &container in &self.order {
+            depths[container.0] = self
+                .contents(container)
                 .iter()
-                .map(move |(_, bag)| (bag.clone(), rule.container.clone()))
+                .map(|(content, _)| depths[content.0] + 1)
+                .max()
+                .unwrap_or(0);
+        }
+        Ok(depths[bag.0])
+    }
+
+    /// Bags that neither contain nor are contained in other bags, ordered by name.
+    pub fn isolated(&self) -> Vec<&str> {
+        self.bags()
+            .filter(|bag| self.contents(*bag).is_empty() && self.containers(*bag).is_empty())
+            .map(|bag| self.name(bag))
+            .sorted()
+            .collect()
+    }
+
+    fn intern(&mut self, name: &str) -> BagId {
+        if let Some(id) = self.id(name) {
+            return id;
+        }
+        let id = BagId(self.names.len());
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        self.contents.push(Vec::new());
+        self.containers.push(Vec::new());
+        id
+    }
+
+    fn add_rule(&mut self, rule: &Rule) {
+        let container = self.intern(rule.container);
+        for (count, bag) in &rule.contents {
+            let bag = self.intern(bag);
+            self.contents[container.0].push((bag, *count));
+            self.containers[bag.0].push((container, *count));
+        }
+    }
+
+    /// Orders the bags by depth first search, failing with the path of the first cycle found.
+    fn topological_order(&self) -> Result<Vec<BagId>, GraphError> {
+        #[derive(Copy, Clone, PartialEq)]
+        enum State {
+            New,
+            Active,
+            Done,
+        }
+
+        let mut states = vec![State::New; self.len()];
+        let mut order = Vec::with_capacity(self.len());
+        for root in self.bags() {
+            if states[root.0] != State::New {
+                continue;
+            }
+            states[root.0] = State::Active;
+            let mut stack = vec![(root, 0)];
+            while let Some(&(bag, edge)) = stack.last() {
+                match self.contents(bag).get(edge) {
+                    Some(&(content, _)) => {
+                        stack.last_mut().unwrap().1 += 1;
+                        match states[content.0] {
+                            State::New => {
+                                states[content.0] = State::Active;
+                                stack.push((content, 0));
+                            }
+                            State::Active => {
+                                return Err(GraphError::Cycle(
+                                    stack
+                                        .iter()
+                                        .map(|(bag, _)| *bag)
+                                        .skip_while(|bag| *bag != content)
+                                        .chain(Some(content))
+                                        .map(|bag| self.name(bag).to_string())
+                                        .collect(),
+                                ));
+                            }
+                            State::Done => {}
+                        }
+                    }
+                    None => {
+                        states[bag.0] = State::Done;
+                        order.push(bag);
+                        stack.pop();
+                    }
+                }
+            }
+        }
+        Ok(order)
+    }
+}
+
+impl TryFrom<&[Rule<'_>]> for BagGraph {
+    type Error = GraphError;
+
+    fn try_from(rules: &[Rule]) -> Result<Self, Self::Error> {
+        let mut graph = BagGraph::default();
+        let mut containers = HashSet::new();
+        for rule in rules {
+            if !containers.insert(rule.container) {
+                return Err(GraphError::DuplicateBag(rule.container.to_string()));
+            }
+            graph.add_rule(rule);
+        }
+        graph.order = graph.topological_order()?;
+        Ok(graph)
+    }
+}
+
+impl TryFrom<&RuleSet> for BagGraph {
+    type Error = GraphError;
+
+    fn try_from(rule_set: &RuleSet) -> Result<Self, Self::Error> {
+        BagGraph::try_from(
+            rule_set
+                .rules
+                .iter()
+                .map(BagRule::as_rule)
+                .collect_vec()
+                .as_slice(),
+        )
+    }
+}
+
+impl FromStr for BagGraph {
+    type Err = GraphError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        BagGraph::try_from(read_rules(input)?.as_slice())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Highlight {
+    Ancestors,
+    Descendants,
+}
+
+/// What to include in and emphasize when exporting a [`BagGraph`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportOptions {
+    /// Highlights the bag together with its ancestors or descendants.
+    pub highlight: Option<(String, Highlight)>,
+    /// Restricts the export to this bag and the bags inside it.
+    pub reachable_from: Option<String>,
+}
+
+struct ExportSelection {
+    included: Vec<bool>,
+    highlighted: Vec<bool>,
+    selected: Option<BagId>,
+}
+
+impl BagGraph {
+    fn select(&self, options: &ExportOptions) -> Result<ExportSelection, GraphError> {
+        let included = match &options.reachable_from {
+            Some(root) => self.reachable(self.bag(root)?, Self::contents),
+            None => vec![true; self.len()],
+        };
+        let (highlighted, selected) = match &options.highlight {
+            Some((name, highlight)) => {
+                let bag = self.bag(name)?;
+                let edges = match highlight {
+                    Highlight::Ancestors => Self::containers,
+                    Highlight::Descendants => Self::contents,
+                };
+                (self.reachable(bag, edges), Some(bag))
+            }
+            None => (vec![false; self.len()], None),
+        };
+        Ok(ExportSelection {
+            included,
+            highlighted,
+            selected,
         })
-        .into_group_map();
-    count_containment_options(&contained_in)
+    }
+
+    fn included_edges<'a>(
+        &'a self,
+        selection: &'a ExportSelection,
+    ) -> impl Iterator<Item = (BagId, BagId, usize)> + 'a {
+        self.bags()
+            .filter(move |bag| selection.included[bag.0])
+            .flat_map(move |container| {
+                self.contents(container)
+                    .iter()
+                    .map(move |(content, count)| (container, *content, *count))
+            })
+    }
+
+    /// Writes the graph in the Graphviz DOT language, with edges labelled by bag counts.
+    pub fn to_dot(&self, options: &ExportOptions) -> Result<String, GraphError> {
+        let selection = self.select(options)?;
+        let quote = |bag: BagId| format!("\"{}\"", self.name(bag).replace('"', "\\\""));
+        let mut dot = String::from("digraph bags {\n");
+        for bag in self.bags().filter(|bag| selection.included[bag.0]) {
+            dot += &match (
+                selection.selected == Some(bag),
+                selection.highlighted[bag.0],
+            ) {
+                (true, _) => format!("    {} [style=filled, fillcolor=gold];\n", quote(bag)),
+                (false, true) => {
+                    format!("    {} [style=filled, fillcolor=lightblue];\n", quote(bag))
+                }
+                (false, false) => format!("    {};\n", quote(bag)),
+            };
+        }
+        for (container, content, count) in self.included_edges(&selection) {
+            dot += &format!(
+                "    {} -> {} [label={}];\n",
+                quote(container),
+                quote(content),
+                count
+            );
+        }
+        dot += "}\n";
+        Ok(dot)
+    }
+
+    /// Writes the graph as a Mermaid flowchart, with edges labelled by bag counts.
+    pub fn to_mermaid(&self, options: &ExportOptions) -> Result<String, GraphError> {
+        let selection = self.select(options)?;
+        let id = |bag: BagId| format!("b{}", bag.0);
+        let mut mermaid = String::from("graph TD\n");
+        for bag in self.bags().filter(|bag| selection.included[bag.0]) {
+            mermaid += &format!(
+                "    {}[\"{}\"]\n",
+                id(bag),
+                self.name(bag).replace('"', "#quot;")
+            );
+        }
+        for (container, content, count) in self.included_edges(&selection) {
+            mermaid += &format!("    {} -->|{}| {}\n", id(container), count, id(content));
+        }
+        if let Some(selected) = selection.selected {
+            let highlighted = self
+                .bags()
+                .filter(|bag| {
+                    *bag != selected && selection.included[bag.0] && selection.highlighted[bag.0]
+                })
+                .map(id)
+                .join(",");
+            mermaid += "    classDef selected fill:gold\n";
+            mermaid += "    classDef highlighted fill:lightblue\n";
+            if selection.included[selected.0] {
+                mermaid += &format!("    class {} selected\n", id(selected));
+            }
+            if !highlighted.is_empty() {
+                mermaid += &format!("    class {} highlighted\n", highlighted);
+            }
+        }
+        Ok(mermaid)
+    }
 }
 
-#[aoc_generator(day7, part1, direct)]
-fn read_map(input: &str) -> HashMap<String, Vec<String>> {
-    input
-        .lines()
-        .flat_map(|line| split_contained_pairs(line))
-        .map(|(key, value)| (key.to_string(), value.to_string()))
-        .into_group_map()
+fn read_map(input: &str) -> Result<HashMap<&str, Vec<&str>>, ParseError> {
+    let mut contained_in: HashMap<&str, Vec<&str>> = HashMap::new();
+    for line in input.lines() {
+        let rule = Rule::parse(line)?;
+        for (_, bag) in rule.contents {
+            contained_in.entry(bag).or_default().push(rule.container);
+        }
+    }
+    Ok(contained_in)
 }
 
+/// Solves part 1 straight from the input, without copying any bag names.
+///
+/// Declared before the day's generator, so that the runner hands over the raw input.
 #[aoc(day7, part1, direct)]
-fn count_containment_options(contained_in: &HashMap<String, Vec<String>>) -> usize {
-    let mut bags: HashSet<Bag> = HashSet::new();
-    let mut queue: VecDeque<Bag> = VecDeque::new();
-    queue.push_back("shiny gold".to_string());
+fn solve_direct(input: &str) -> Result<usize, ParseError> {
+    read_map(input).map(|contained_in| count_containment_options(&contained_in))
+}
+
+fn count_containment_options(contained_in: &HashMap<&str, Vec<&str>>) -> usize {
+    let mut bags: HashSet<&str> = HashSet::new();
+    let mut queue: VecDeque<&str> = VecDeque::new();
+    queue.push_back("shiny gold");
     while let Some(bag) = queue.pop_front() {
-        if let Some(containers) = contained_in.get(&bag) {
-            queue.extend(
-                containers
-                    .iter()
-                    .filter(|container| !bags.contains(*container))
-                    .cloned(),
-            );
-            bags.extend(containers.iter().cloned());
+        if let Some(containers) = contained_in.get(bag) {
+            for container in containers {
+                if bags.insert(container) {
+                    queue.push_back(container);
+                }
+            }
         }
     }
     bags.len()
 }
 
-fn split_contained_pairs(rule: &str) -> impl Iterator<Item = (&str, &str)> + '_ {
-    let mut parts = rule.splitn(2, " bags contain ");
-    let container = parts.next().unwrap();
-    let parts = parts.next().unwrap_or("").trim_end_matches('.');
-    parts
-        .split(", ")
-        .map(move |part| (normalize(part), container))
+#[aoc_generator(day7)]
+fn read_graph(input: &str) -> Result<BagGraph, GraphError> {
+    input.parse()
 }
 
-fn normalize(part: &str) -> &str {
-    part.trim_end_matches("bag")
-        .trim_end_matches("bags")
-        .trim_start_matches(|ch: char| ch.is_digit(10))
-        .trim()
+/// Finds the containers of each bag by scanning the contents of all bags, without the reverse edges.
+#[aoc(day7, part1, Rules)]
+fn part1_rules(graph: &BagGraph) -> usize {
+    let mut bags: HashSet<BagId> = HashSet::new();
+    let mut queue: VecDeque<BagId> = VecDeque::new();
+    queue.extend(graph.id("shiny gold"));
+    while let Some(bag) = queue.pop_front() {
+        let containers = graph
+            .bags()
+            .filter(|container| {
+                graph
+                    .contents(*container)
+                    .iter()
+                    .any(|(content, _)| *content == bag)
+            })
+            .filter(|container| bags.insert(*container))
+            .collect_vec();
+        queue.extend(containers);
+    }
+    bags.len()
 }
 
-fn count_bags(rules: &[Rule], bag: &str) -> usize {
-    rules
-        .iter()
-        .find(|rule| rule.container == *bag)
-        .map(|rule| {
-            rule.contents
-                .iter()
-                .map(|(count, bag)| count + (count_bags(rules, bag) * count))
-                .sum::<usize>()
-        })
-        .unwrap_or(0)
+#[aoc(day7, part1, Map)]
+fn part1(graph: &BagGraph) -> usize {
+    let mut bags: HashSet<BagId> = HashSet::new();
+    let mut queue: VecDeque<BagId> = VecDeque::new();
+    queue.extend(graph.id("shiny gold"));
+    while let Some(bag) = queue.pop_front() {
+        for (container, _) in graph.containers(bag) {
+            if bags.insert(*container) {
+                queue.push_back(*container);
+            }
+        }
+    }
+    bags.len()
+}
+
+fn count_bags(graph: &BagGraph, bag: &str) -> Result<usize, GraphError> {
+    graph.count_contents(graph.bag(bag)?)
 }
 
 #[aoc(day7, part2)]
-fn part2(rules: &[Rule]) -> usize {
-    count_bags(&rules, "shiny gold")
+fn part2(graph: &BagGraph) -> Result<usize, GraphError> {
+    count_bags(graph, "shiny gold")
 }
 
 #[cfg(test)]
@@ -173,8 +792,12 @@ dark violet bags contain no other bags.";
 
     const INPUT: &str = include_str!("../input/2020/day7.txt");
 
-    fn solve(solver: fn(&[Rule]) -> usize, input: &str) -> Result<usize, ParseError> {
-        read_rules(input).map(|rules| solver(&rules))
+    fn solve(solver: fn(&BagGraph) -> usize, input: &str) -> Result<usize, GraphError> {
+        read_graph(input).map(|graph| solver(&graph))
+    }
+
+    fn solve_with_counts(input: &str) -> Result<usize, GraphError> {
+        read_graph(input).and_then(|graph| part2(&graph))
     }
 
     #[test]
@@ -188,7 +811,10 @@ dark violet bags contain no other bags.";
     }
     #[test]
     fn solve_example_without_parsing() {
-        assert_eq!(count_containment_options(&read_map(EXAMPLE)), 4);
+        assert_eq!(
+            read_map(EXAMPLE).map(|contained_in| count_containment_options(&contained_in)),
+            Ok(4)
+        );
     }
 
     #[test]
@@ -203,31 +829,428 @@ dark violet bags contain no other bags.";
 
     #[test]
     fn solve_part1_without_parsing() {
-        assert_eq!(count_containment_options(&read_map(INPUT)), 155);
+        assert_eq!(
+            read_map(INPUT).map(|contained_in| count_containment_options(&contained_in)),
+            Ok(155)
+        );
     }
 
     #[test]
     fn counts_bags_in_example() {
-        let rules = read_rules(EXAMPLE).expect("Failed to read example rules");
-        assert_eq!(count_bags(&rules, "faded blue"), 0);
-        assert_eq!(count_bags(&rules, "dotted black"), 0);
-        assert_eq!(count_bags(&rules, "vibrant plum"), 11);
-        assert_eq!(count_bags(&rules, "dark olive"), 7);
-        assert_eq!(count_bags(&rules, "shiny gold"), 32);
+        let graph = read_graph(EXAMPLE).expect("Failed to read example rules");
+        assert_eq!(count_bags(&graph, "faded blue"), Ok(0));
+        assert_eq!(count_bags(&graph, "dotted black"), Ok(0));
+        assert_eq!(count_bags(&graph, "vibrant plum"), Ok(11));
+        assert_eq!(count_bags(&graph, "dark olive"), Ok(7));
+        assert_eq!(count_bags(&graph, "shiny gold"), Ok(32));
     }
 
     #[test]
     fn solve_example_part2() {
-        assert_eq!(solve(part2, EXAMPLE), Ok(32));
+        assert_eq!(solve_with_counts(EXAMPLE), Ok(32));
     }
 
     #[test]
     fn solve_second_example() {
-        assert_eq!(solve(part2, EXAMPLE2), Ok(126));
+        assert_eq!(solve_with_counts(EXAMPLE2), Ok(126));
     }
 
     #[test]
     fn solve_part2() {
-        assert_eq!(solve(part2, INPUT), Ok(54803));
+        assert_eq!(solve_with_counts(INPUT), Ok(54803));
+    }
+
+    #[test]
+    fn interns_bag_names() {
+        let graph = read_graph(EXAMPLE).expect("Failed to read example rules");
+        assert_eq!(graph.len(), 9);
+        let gold = graph.id("shiny gold").expect("Missing shiny gold");
+        assert_eq!(graph.name(gold), "shiny gold");
+        assert_eq!(graph.id("no other"), None);
+    }
+
+    #[test]
+    fn links_contents_and_containers() {
+        let graph = read_graph(EXAMPLE).expect("Failed to read example rules");
+        let names = |edges: &[(BagId, usize)]| {
+            edges
+                .iter()
+                .map(|(bag, count)| (graph.name(*bag).to_string(), *count))
+                .collect_vec()
+        };
+        let gold = graph.id("shiny gold").expect("Missing shiny gold");
+        assert_eq!(
+            names(graph.contents(gold)),
+            vec![
+                ("dark olive".to_string(), 1),
+                ("vibrant plum".to_string(), 2)
+            ]
+        );
+        assert_eq!(
+            names(graph.containers(gold)),
+            vec![
+                ("bright white".to_string(), 1),
+                ("muted yellow".to_string(), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_cycles_with_their_path() {
+        assert_eq!(
+            read_graph(
+                "\
+light red bags contain 1 dark olive bag.
+dark olive bags contain 2 vibrant plum bags.
+vibrant plum bags contain 1 faded blue bag, 1 dark olive bag.
+faded blue bags contain no other bags."
+            ),
+            Err(GraphError::Cycle(vec![
+                "dark olive".to_string(),
+                "vibrant plum".to_string(),
+                "dark olive".to_string()
+            ]))
+        );
+        assert_eq!(
+            read_graph("shiny gold bags contain 1 shiny gold bag.")
+                .map_err(|error| error.to_string()),
+            Err("Bags contain themselves: shiny gold -> shiny gold".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_repeated_rules() {
+        assert_eq!(
+            read_graph(
+                "\
+shiny gold bags contain 1 dark olive bag.
+dark olive bags contain no other bags.
+shiny gold bags contain 2 vibrant plum bags."
+            ),
+            Err(GraphError::DuplicateBag("shiny gold".to_string()))
+        );
+        assert_eq!(
+            read_graph(
+                "\
+faded blue bags contain no other bags.
+faded blue bags contain no other bags."
+            ),
+            Err(GraphError::DuplicateBag("faded blue".to_string()))
+        );
+    }
+
+    #[test]
+    fn counts_deep_shared_graphs() {
+        let rules = (0..40)
+            .map(|layer| {
+                format!(
+                    "left l{0} bags contain 1 left l{1} bag, 1 right l{1} bag.\n\
+                     right l{0} bags contain 1 left l{1} bag, 1 right l{1} bag.",
+                    layer,
+                    layer + 1
+                )
+            })
+            .join("\n");
+        let graph = read_graph(&rules).expect("Failed to read rules");
+        assert_eq!(count_bags(&graph, "left l0"), Ok((1 << 41) - 2));
+    }
+
+    #[test]
+    fn reports_overflow() {
+        let graph = read_graph(
+            "\
+shiny gold bags contain 99999999999 dark olive bags.
+dark olive bags contain 99999999999 vibrant plum bags.
+vibrant plum bags contain 9999999999 faded blue bags.",
+        )
+        .expect("Failed to read rules");
+        assert_eq!(
+            count_bags(&graph, "shiny gold"),
+            Err(GraphError::Overflow("dark olive".to_string()))
+        );
+        assert_eq!(count_bags(&graph, "vibrant plum"), Ok(9999999999));
+    }
+
+    #[test]
+    fn rejects_unknown_bags() {
+        let graph = read_graph(EXAMPLE).expect("Failed to read example rules");
+        let unknown = || GraphError::UnknownBag("plaid red".to_string());
+        assert_eq!(count_bags(&graph, "plaid red"), Err(unknown()));
+        assert_eq!(graph.ancestors("plaid red"), Err(unknown()));
+        assert_eq!(graph.descendants("plaid red"), Err(unknown()));
+        assert_eq!(graph.paths("shiny gold", "plaid red"), Err(unknown()));
+        assert_eq!(graph.depth("plaid red"), Err(unknown()));
+    }
+
+    #[test]
+    fn finds_ancestors() {
+        let graph = read_graph(EXAMPLE).expect("Failed to read example rules");
+        assert_eq!(
+            graph.ancestors("shiny gold"),
+            Ok(vec![
+                "bright white",
+                "dark orange",
+                "light red",
+                "muted yellow"
+            ])
+        );
+        assert_eq!(graph.ancestors("light red"), Ok(vec![]));
+    }
+
+    #[test]
+    fn finds_descendants_with_multiplicities() {
+        let graph = read_graph(EXAMPLE).expect("Failed to read example rules");
+        let descendants = graph
+            .descendants("shiny gold")
+            .expect("Failed to find descendants");
+        assert_eq!(
+            descendants.clone().into_iter().collect_vec(),
+            vec![
+                ("dark olive", 1),
+                ("dotted black", 16),
+                ("faded blue", 13),
+                ("vibrant plum", 2)
+            ]
+        );
+        assert_eq!(descendants.values().sum::<usize>(), 32);
+    }
+
+    #[test]
+    fn lists_all_paths_between_bags() {
+        let graph = read_graph(EXAMPLE).expect("Failed to read example rules");
+        assert_eq!(
+            graph.paths("light red", "shiny gold"),
+            Ok(vec![
+                vec!["light red", "bright white", "shiny gold"],
+                vec!["light red", "muted yellow", "shiny gold"]
+            ])
+        );
+        assert_eq!(graph.paths("shiny gold", "light red"), Ok(vec![]));
+        assert_eq!(
+            graph.paths("faded blue", "faded blue"),
+            Ok(vec![vec!["faded blue"]])
+        );
+    }
+
+    #[test]
+    fn measures_nesting_depth() {
+        let graph = read_graph(EXAMPLE).expect("Failed to read example rules");
+        assert_eq!(graph.depth("light red"), Ok(4));
+        assert_eq!(graph.depth("shiny gold"), Ok(2));
+        assert_eq!(graph.depth("faded blue"), Ok(0));
+        let graph = read_graph(EXAMPLE2).expect("Failed to read second example rules");
+        assert_eq!(graph.depth("shiny gold"), Ok(6));
+    }
+
+    #[test]
+    fn finds_isolated_bags() {
+        let graph = read_graph(
+            "\
+shiny gold bags contain 2 dark red bags.
+plaid red bags contain no other bags.
+dark red bags contain no other bags.",
+        )
+        .expect("Failed to read rules");
+        assert_eq!(graph.isolated(), vec!["plaid red"]);
+    }
+
+    const SMALL_EXAMPLE: &str = "\
+bright white bags contain 1 shiny gold bag.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain no other bags.
+vibrant plum bags contain no other bags.";
+
+    #[test]
+    fn exports_dot() {
+        let graph = read_graph(SMALL_EXAMPLE).expect("Failed to read rules");
+        assert_eq!(
+            graph.to_dot(&ExportOptions::default()),
+            Ok("\
+digraph bags {
+    \"bright white\";
+    \"shiny gold\";
+    \"dark olive\";
+    \"vibrant plum\";
+    \"bright white\" -> \"shiny gold\" [label=1];
+    \"shiny gold\" -> \"dark olive\" [label=1];
+    \"shiny gold\" -> \"vibrant plum\" [label=2];
+}
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn exports_highlighted_dot_subgraph() {
+        let graph = read_graph(SMALL_EXAMPLE).expect("Failed to read rules");
+        assert_eq!(
+            graph.to_dot(&ExportOptions {
+                highlight: Some(("dark olive".to_string(), Highlight::Ancestors)),
+                reachable_from: Some("shiny gold".to_string()),
+            }),
+            Ok("\
+digraph bags {
+    \"shiny gold\" [style=filled, fillcolor=lightblue];
+    \"dark olive\" [style=filled, fillcolor=gold];
+    \"vibrant plum\";
+    \"shiny gold\" -> \"dark olive\" [label=1];
+    \"shiny gold\" -> \"vibrant plum\" [label=2];
+}
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn exports_mermaid() {
+        let graph = read_graph(SMALL_EXAMPLE).expect("Failed to read rules");
+        assert_eq!(
+            graph.to_mermaid(&ExportOptions {
+                highlight: Some(("shiny gold".to_string(), Highlight::Descendants)),
+                reachable_from: None,
+            }),
+            Ok("\
+graph TD
+    b0[\"bright white\"]
+    b1[\"shiny gold\"]
+    b2[\"dark olive\"]
+    b3[\"vibrant plum\"]
+    b0 -->|1| b1
+    b1 -->|1| b2
+    b1 -->|2| b3
+    classDef selected fill:gold
+    classDef highlighted fill:lightblue
+    class b1 selected
+    class b2,b3 highlighted
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_exports_of_unknown_bags() {
+        let graph = read_graph(SMALL_EXAMPLE).expect("Failed to read rules");
+        assert_eq!(
+            graph.to_dot(&ExportOptions {
+                highlight: None,
+                reachable_from: Some("plaid red".to_string()),
+            }),
+            Err(GraphError::UnknownBag("plaid red".to_string()))
+        );
+    }
+
+    #[test]
+    fn borrows_bag_names_from_input() {
+        let line = "bright white bags contain 1 shiny gold bag, 2 faded blue bags.";
+        let rule = Rule::parse(line).expect("Failed to parse rule");
+        assert_eq!(rule.container, "bright white");
+        assert_eq!(rule.contents, vec![(1, "shiny gold"), (2, "faded blue")]);
+        assert!(std::ptr::eq(rule.container, &line[..12]));
+    }
+
+    #[test]
+    fn does_not_map_phantom_bags() {
+        let contained_in = read_map(EXAMPLE).expect("Failed to read example rules");
+        assert!(!contained_in.contains_key("no other"));
+        assert_eq!(contained_in["faded blue"].len(), 3);
+    }
+
+    #[test]
+    fn rejects_malformed_rules_in_every_reader() {
+        for rule in &[
+            "bright white bags hold 1 shiny gold bag.",
+            "bright white bags contain 1 shiny gold bag",
+            "bright white bags contain one shiny gold bag.",
+            "bright white bags contain 1 shiny gold.",
+            "bright white bags contain 1 gold bag.",
+            "white bags contain 1 shiny gold bag.",
+            "bright white bags contain 1 shiny  gold bag.",
+        ] {
+            assert!(read_map(rule).is_err(), "{}", rule);
+            assert!(read_graph(rule).is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn round_trips_rules() {
+        let input = format!("{}\n", EXAMPLE);
+        let rule_set = input
+            .parse::<RuleSet>()
+            .expect("Failed to read example rules");
+        assert_eq!(rule_set.to_string(), input);
+        let rule_set = INPUT.parse::<RuleSet>().expect("Failed to read rules");
+        assert_eq!(rule_set.to_string(), INPUT);
+    }
+
+    #[test]
+    fn edits_rules() {
+        let mut rule_set = SMALL_EXAMPLE
+            .parse::<RuleSet>()
+            .expect("Failed to read rules");
+        rule_set.add_bag("plaid red").expect("Failed to add bag");
+        rule_set
+            .set_count("bright white", "plaid red", 1)
+            .expect("Failed to add content");
+        rule_set
+            .set_count("bright white", "shiny gold", 3)
+            .expect("Failed to change count");
+        rule_set
+            .set_count("shiny gold", "dark olive", 0)
+            .expect("Failed to remove content");
+        rule_set
+            .rename("vibrant plum", "muted yellow")
+            .expect("Failed to rename bag");
+        rule_set
+            .remove_bag("dark olive")
+            .expect("Failed to remove bag");
+        assert_eq!(
+            rule_set.to_string(),
+            "\
+bright white bags contain 3 shiny gold bags, 1 plaid red bag.
+shiny gold bags contain 2 muted yellow bags.
+muted yellow bags contain no other bags.
+plaid red bags contain no other bags.
+"
+        );
+        let graph = BagGraph::try_from(&rule_set).expect("Failed to build graph");
+        assert_eq!(count_bags(&graph, "bright white"), Ok(10));
+    }
+
+    #[test]
+    fn rejects_invalid_edits() {
+        let mut rule_set = SMALL_EXAMPLE
+            .parse::<RuleSet>()
+            .expect("Failed to read rules");
+        let unknown = || Err(GraphError::UnknownBag("plaid red".to_string()));
+        assert_eq!(rule_set.remove_bag("plaid red"), unknown());
+        assert_eq!(rule_set.rename("plaid red", "muted yellow"), unknown());
+        assert_eq!(rule_set.set_count("plaid red", "shiny gold", 1), unknown());
+        assert_eq!(
+            rule_set.add_bag("shiny gold"),
+            Err(GraphError::DuplicateBag("shiny gold".to_string()))
+        );
+        assert_eq!(
+            rule_set.rename("shiny gold", "dark olive"),
+            Err(GraphError::DuplicateBag("dark olive".to_string()))
+        );
+        assert_eq!(
+            rule_set.add_bag("gold"),
+            Err(GraphError::ParseError(ParseError::FormatError))
+        );
+        assert_eq!(rule_set.to_string(), format!("{}\n", SMALL_EXAMPLE));
+    }
+
+    #[test]
+    fn reports_cycles_created_by_edits() {
+        let mut rule_set = SMALL_EXAMPLE
+            .parse::<RuleSet>()
+            .expect("Failed to read rules");
+        rule_set
+            .set_count("dark olive", "bright white", 1)
+            .expect("Failed to add content");
+        assert!(matches!(
+            BagGraph::try_from(&rule_set),
+            Err(GraphError::Cycle(_))
+        ));
     }
 }
@@ -2,25 +2,131 @@ use crate::{read_lines, ParseError};
 use itertools::Itertools;
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
 use std::fmt::Debug;
 use std::str::FromStr;
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
-enum ProgramError {
+pub enum ProgramError {
     #[error(transparent)]
     ParseError(#[from] ParseError),
     #[error("Programm terminated unexpectedly")]
     UnexpectedTermination,
     #[error("Unknown operation: {0}")]
     UnknownOperation(String),
+    #[error("Program did not loop: {0:?}")]
+    UnexpectedOutcome(Outcome),
+    #[error("Unknown label: {0}")]
+    UnknownLabel(String),
+    #[error("Label defined twice: {0}")]
+    DuplicateLabel(String),
+    #[error("No single flip makes the program terminate")]
+    NoFix,
+    #[error("Flipping any of these instructions makes the program terminate: {0:?}")]
+    AmbiguousFix(Vec<usize>),
 }
 
-#[derive(Debug, PartialEq)]
-enum ExecutionResult {
-    Terminated(isize),
-    Stopped(isize),
+/// How running a program ended.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Terminated {
+        acc: isize,
+    },
+    /// The instruction at `pc` was about to run a second time.
+    LoopDetected {
+        pc: usize,
+        acc: isize,
+    },
+    JumpOutOfBounds {
+        from: usize,
+        to: isize,
+    },
+    StepLimitExceeded {
+        pc: usize,
+        acc: isize,
+    },
+    /// The instruction at `pc` could not be executed.
+    Faulted {
+        pc: usize,
+        fault: Fault,
+    },
+}
+
+/// Which positions past the last instruction end a program.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Termination {
+    /// Only the position right after the last instruction terminates.
+    Strict,
+    /// Any position after the last instruction terminates.
+    Lenient,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RunOptions {
+    pub termination: Termination,
+    pub step_limit: Option<usize>,
+    /// Stops when an instruction is about to run a second time. Programs using registers to
+    /// count may revisit instructions legitimately, and rely on the step limit instead.
+    pub detect_loops: bool,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            termination: Termination::Strict,
+            step_limit: None,
+            detect_loops: true,
+        }
+    }
+}
+
+impl RunOptions {
+    /// The position to continue at, or `None` if it is out of bounds.
+    fn landing(&self, to: isize, len: usize) -> Option<usize> {
+        match self.termination {
+            _ if to < 0 => None,
+            Termination::Strict if to as usize > len => None,
+            _ => Some(to as usize),
+        }
+    }
+
+    fn exceeds_step_limit(&self, steps: usize) -> bool {
+        self.step_limit.is_some_and(|limit| steps >= limit)
+    }
+}
+
+/// Where execution continues after an instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Next,
+    /// Jumps relative to the instruction.
+    Jump(isize),
+    Halt,
+}
+
+/// Why an instruction could not be executed.
+#[derive(Error, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    #[error("Arithmetic overflow")]
+    Overflow,
+    #[error("Cannot write to an immediate")]
+    NotARegister,
+}
+
+/// The accumulator and the registers `a` to `z`, all starting at zero.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Machine {
+    pub accumulator: isize,
+    pub registers: [isize; 26],
+}
+
+/// An instruction that the [`parsed::Vm`] can execute.
+pub trait Execute {
+    /// Updates the machine and tells where to continue. The [`parsed::Vm`] discards the
+    /// machine of instructions that fail.
+    fn execute(&self, machine: &mut Machine) -> Result<Flow, Fault>;
 }
 
 #[cfg(test)]
@@ -43,37 +149,65 @@ mod trivial {
 
     #[aoc(day8, part1, trivial)]
     fn execute_until_loop(input: &str) -> Result<isize, ProgramError> {
-        match run_first_iteration(input)? {
-            ExecutionResult::Terminated(_) => Err(ProgramError::UnexpectedTermination),
-            ExecutionResult::Stopped(value) => Ok(value),
+        match run_first_iteration(input, RunOptions::default())? {
+            Outcome::LoopDetected { acc, .. } => Ok(acc),
+            Outcome::Terminated { .. } => Err(ProgramError::UnexpectedTermination),
+            outcome => Err(ProgramError::UnexpectedOutcome(outcome)),
         }
     }
 
-    fn run_first_iteration(input: &str) -> Result<ExecutionResult, ProgramError> {
+    fn run_first_iteration(input: &str, options: RunOptions) -> Result<Outcome, ProgramError> {
         let program = input.lines().collect_vec();
-        let mut executed_positions = HashSet::new();
+        let mut executed = vec![false; program.len()];
         let mut accumulator = 0;
         let mut position = 0;
+        let mut steps = 0;
         while position < program.len() {
+            if std::mem::replace(&mut executed[position], true) && options.detect_loops {
+                return Ok(Outcome::LoopDetected {
+                    pc: position,
+                    acc: accumulator,
+                });
+            }
+            if options.exceeds_step_limit(steps) {
+                return Ok(Outcome::StepLimitExceeded {
+                    pc: position,
+                    acc: accumulator,
+                });
+            }
+            steps += 1;
             let (operation, argument) = program[position]
                 .split_whitespace()
                 .collect_tuple()
                 .ok_or(ParseError::FormatError)?;
             let argument = argument.parse::<isize>().map_err(ParseError::from)?;
-            match operation {
-                "acc" => {
-                    accumulator += argument;
-                    position += 1;
-                }
-                "jmp" => position = (position as isize + argument) as usize,
-                "nop" => position += 1,
+            let (to, acc) = match operation {
+                "acc" => (
+                    (position as isize).checked_add(1),
+                    accumulator.checked_add(argument),
+                ),
+                "jmp" => ((position as isize).checked_add(argument), Some(accumulator)),
+                "nop" => ((position as isize).checked_add(1), Some(accumulator)),
                 _ => return Err(ProgramError::UnknownOperation(operation.to_string())),
-            }
-            if !executed_positions.insert(position) {
-                return Ok(ExecutionResult::Stopped(accumulator));
-            }
+            };
+            let (to, acc) = match to.zip(acc) {
+                Some(next) => next,
+                None => {
+                    return Ok(Outcome::Faulted {
+                        pc: position,
+                        fault: Fault::Overflow,
+                    });
+                }
+            };
+            accumulator = acc;
+            position = match options.landing(to, program.len()) {
+                Some(position) => position,
+                None => {
+                    return Ok(Outcome::JumpOutOfBounds { from: position, to });
+                }
+            };
         }
-        Ok(ExecutionResult::Terminated(accumulator))
+        Ok(Outcome::Terminated { acc: accumulator })
     }
 
     fn fix_program(input: &str) -> impl Iterator<Item = String> + '_ {
@@ -90,12 +224,10 @@ mod trivial {
     #[aoc(day8, part2, trivial)]
     fn find_termination_fix(input: &str) -> Option<isize> {
         fix_program(input)
-            .map(|fix| run_first_iteration(&fix))
-            .find_map(|result| {
-                result.ok().and_then(|result| match result {
-                    ExecutionResult::Terminated(result) => Some(result),
-                    ExecutionResult::Stopped(_) => None,
-                })
+            .map(|fix| run_first_iteration(&fix, RunOptions::default()))
+            .find_map(|result| match result {
+                Ok(Outcome::Terminated { acc }) => Some(acc),
+                _ => None,
             })
     }
 
@@ -121,10 +253,118 @@ mod trivial {
         fn solve_part2() {
             assert_eq!(find_termination_fix(INPUT), Some(2060));
         }
+
+        #[test]
+        fn reports_jumps_out_of_bounds() {
+            let strict = RunOptions::default();
+            let lenient = RunOptions {
+                termination: Termination::Lenient,
+                ..RunOptions::default()
+            };
+            assert_eq!(
+                run_first_iteration("acc +1\njmp -2", strict),
+                Ok(Outcome::JumpOutOfBounds { from: 1, to: -1 })
+            );
+            assert_eq!(
+                run_first_iteration("acc +1\njmp -2", lenient),
+                Ok(Outcome::JumpOutOfBounds { from: 1, to: -1 })
+            );
+            assert_eq!(
+                run_first_iteration("acc +1\njmp +2", strict),
+                Ok(Outcome::JumpOutOfBounds { from: 1, to: 3 })
+            );
+            assert_eq!(
+                run_first_iteration("acc +1\njmp +2", lenient),
+                Ok(Outcome::Terminated { acc: 1 })
+            );
+            assert_eq!(
+                execute_until_loop("jmp -1"),
+                Err(ProgramError::UnexpectedOutcome(Outcome::JumpOutOfBounds {
+                    from: 0,
+                    to: -1
+                }))
+            );
+        }
+
+        #[test]
+        fn stops_at_step_limit() {
+            let options = RunOptions {
+                step_limit: Some(3),
+                ..RunOptions::default()
+            };
+            assert_eq!(
+                run_first_iteration(EXAMPLE, options),
+                Ok(Outcome::StepLimitExceeded { pc: 6, acc: 1 })
+            );
+        }
+
+        #[test]
+        fn agrees_with_parsed_runner() {
+            let programs = [
+                EXAMPLE,
+                "acc +1\njmp -2",
+                "acc +1\njmp +2",
+                "jmp -1",
+                "jmp +0",
+                "acc +9223372036854775807\nacc +1",
+                "acc -9223372036854775807\nacc -2",
+                "nop +0\njmp +9223372036854775807",
+            ];
+            let options = [
+                RunOptions::default(),
+                RunOptions {
+                    termination: Termination::Lenient,
+                    ..RunOptions::default()
+                },
+                RunOptions {
+                    step_limit: Some(0),
+                    ..RunOptions::default()
+                },
+                RunOptions {
+                    step_limit: Some(3),
+                    ..RunOptions::default()
+                },
+                RunOptions {
+                    step_limit: Some(10),
+                    detect_loops: false,
+                    ..RunOptions::default()
+                },
+            ];
+            for program in &programs {
+                let instructions = read_lines(program).expect("Failed to read program");
+                for options in &options {
+                    assert_eq!(
+                        run_first_iteration(program, *options),
+                        Ok(parsed::execute(&instructions, *options)),
+                        "{:?} with {:?}",
+                        program,
+                        options
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn faults_on_overflow() {
+            assert_eq!(
+                run_first_iteration("acc +9223372036854775807\nacc +1", RunOptions::default()),
+                Ok(Outcome::Faulted {
+                    pc: 1,
+                    fault: Fault::Overflow
+                })
+            );
+            assert_eq!(
+                run_first_iteration("nop +0\njmp +9223372036854775807", RunOptions::default()),
+                Ok(Outcome::Faulted {
+                    pc: 1,
+                    fault: Fault::Overflow
+                })
+            );
+        }
     }
 }
 
-mod parsed {
+pub mod parsed {
     use super::*;
 
     #[aoc_generator(day8)]
@@ -132,32 +372,39 @@ mod parsed {
         read_lines(input)
     }
 
-    fn run_first_iteration(program: &[Instruction]) -> ExecutionResult {
-        let mut executed_positions = HashSet::new();
-        let mut accumulator = 0;
-        let mut position = 0;
-        while position < program.len() {
-            let instruction = &program[position];
-            match instruction.operation {
-                Operation::Acc => {
-                    accumulator += instruction.argument;
-                    position += 1;
-                }
-                Operation::Jmp => position = (position as isize + instruction.argument) as usize,
-                Operation::Nop => position += 1,
-            }
-            if !executed_positions.insert(position) {
-                return ExecutionResult::Stopped(accumulator);
+    /// Runs the program until it loops or ends.
+    pub fn execute(program: &[Instruction], options: RunOptions) -> Outcome {
+        run(program, options).0
+    }
+
+    /// Runs a program of any instruction set until it loops or ends, returning the final
+    /// machine state.
+    pub fn run<I: Execute + Clone>(program: &[I], options: RunOptions) -> (Outcome, Machine) {
+        let mut vm = Vm::with_options(program, options);
+        let stop = vm.run();
+        let (pc, acc) = (vm.pc(), vm.accumulator());
+        let outcome = match stop {
+            Stop::Terminated => Outcome::Terminated { acc },
+            Stop::LoopDetected => Outcome::LoopDetected { pc, acc },
+            Stop::JumpOutOfBounds { from, to } => Outcome::JumpOutOfBounds { from, to },
+            Stop::StepLimitExceeded => Outcome::StepLimitExceeded { pc, acc },
+            Stop::Fault(fault) => Outcome::Faulted { pc, fault },
+            Stop::Breakpoint(_) | Stop::Watch(_) | Stop::Condition => {
+                unreachable!("Stopped without breakpoints, watches or conditions")
             }
-        }
-        ExecutionResult::Terminated(accumulator)
+        };
+        (outcome, *vm.machine())
+    }
+
+    fn run_first_iteration(program: &[Instruction]) -> Outcome {
+        execute(program, RunOptions::default())
     }
 
     #[aoc(day8, part1)]
     fn execute_until_loop(program: &[Instruction]) -> Option<isize> {
         match run_first_iteration(program) {
-            ExecutionResult::Terminated(_) => None,
-            ExecutionResult::Stopped(value) => Some(value),
+            Outcome::LoopDetected { acc, .. } => Some(acc),
+            _ => None,
         }
     }
 
@@ -165,17 +412,88 @@ mod parsed {
         program
             .iter()
             .enumerate()
-            .filter_map(move |(position, instruction)| match instruction.operation {
-                // Converting a nop to a jmp 1 changes nothing. Converting a nop to a jmp 0 creates an endless loop.
-                Operation::Nop if instruction.argument != 1 && instruction.argument != 0 => {
-                    Some(replace_operation(program, position, Operation::Jmp))
-                }
-                // Converting a jmp 1 to a nop changes nothing
-                Operation::Jmp if instruction.argument != 1 => {
-                    Some(replace_operation(program, position, Operation::Nop))
-                }
+            .filter(|(_, instruction)| skip_reason(instruction).is_none())
+            .filter_map(move |(position, instruction)| {
+                let flipped = instruction.flipped()?;
+                Some(replace_operation(program, position, flipped.operation))
+            })
+    }
+
+    /// Why [`fix_program`] does not try flipping an instruction.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum SkipReason {
+        /// Converting a nop to a jmp 0 creates an endless loop.
+        JumpsToItself,
+        /// Converting a nop to a jmp 1 or a jmp 1 to a nop changes nothing.
+        ChangesNothing,
+    }
+
+    fn skip_reason(instruction: &Instruction) -> Option<SkipReason> {
+        match (instruction.operation, instruction.argument) {
+            (Operation::Nop, 0) => Some(SkipReason::JumpsToItself),
+            (Operation::Nop, 1) | (Operation::Jmp, 1) => Some(SkipReason::ChangesNothing),
+            _ => None,
+        }
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum FixResult {
+        Skipped(SkipReason),
+        Ran(Outcome),
+    }
+
+    /// A jmp or nop that might be the corrupted instruction, with what flipping it does.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct FixCandidate {
+        pub index: usize,
+        pub instruction: Instruction,
+        pub result: FixResult,
+    }
+
+    /// Every jmp and nop of the program, with the outcome of running the program with it
+    /// flipped, or why [`fix_program`] does not try it.
+    pub fn fix_candidates(program: &[Instruction]) -> Vec<FixCandidate> {
+        program
+            .iter()
+            .enumerate()
+            .filter_map(|(index, instruction)| {
+                let flipped = instruction.flipped()?;
+                let result = match skip_reason(instruction) {
+                    Some(reason) => FixResult::Skipped(reason),
+                    None => FixResult::Ran(run_first_iteration(&replace_operation(
+                        program,
+                        index,
+                        flipped.operation,
+                    ))),
+                };
+                Some(FixCandidate {
+                    index,
+                    instruction: *instruction,
+                    result,
+                })
+            })
+            .collect()
+    }
+
+    /// The only flip that makes the program terminate, failing if there is none or several.
+    pub fn unique_fix(program: &[Instruction]) -> Result<Repair, ProgramError> {
+        let fixes = fix_candidates(program)
+            .into_iter()
+            .filter_map(|candidate| match candidate.result {
+                FixResult::Ran(Outcome::Terminated { acc }) => Some(Repair {
+                    index: candidate.index,
+                    accumulator: acc,
+                }),
                 _ => None,
             })
+            .collect_vec();
+        match fixes.as_slice() {
+            [fix] => Ok(*fix),
+            [] => Err(ProgramError::NoFix),
+            _ => Err(ProgramError::AmbiguousFix(
+                fixes.iter().map(|fix| fix.index).collect(),
+            )),
+        }
     }
 
     fn replace_operation(
@@ -195,23 +513,165 @@ mod parsed {
     fn find_termination_fix(program: &[Instruction]) -> Option<isize> {
         fix_program(program)
             .map(|fix| run_first_iteration(&fix))
-            .find_map(|result| match result {
-                ExecutionResult::Terminated(result) => Some(result),
-                ExecutionResult::Stopped(_) => None,
+            .find_map(|outcome| match outcome {
+                Outcome::Terminated { acc } => Some(acc),
+                _ => None,
             })
     }
 
-    #[derive(Copy, Clone, Debug)]
-    enum Operation {
+    /// The instruction to flip between jmp and nop, and the accumulator once the repaired
+    /// program terminates.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct Repair {
+        pub index: usize,
+        pub accumulator: isize,
+    }
+
+    /// The position after the instruction at the given position with strict termination,
+    /// or `None` if it jumps out of bounds.
+    pub(super) fn successor(
+        program: &[Instruction],
+        instruction: &Instruction,
+        pc: usize,
+    ) -> Option<usize> {
+        RunOptions::default().landing(instruction.target(pc)?, program.len())
+    }
+
+    /// Marks the instructions from which the program runs to termination, by searching
+    /// backwards from the instructions leaving the program.
+    pub(super) fn terminating(program: &[Instruction]) -> Vec<bool> {
+        let mut predecessors = vec![Vec::new(); program.len()];
+        let mut stack = Vec::new();
+        for (pc, instruction) in program.iter().enumerate() {
+            match successor(program, instruction, pc) {
+                Some(next) if next == program.len() => stack.push(pc),
+                Some(next) => predecessors[next].push(pc),
+                None => {}
+            }
+        }
+        let mut terminating = vec![false; program.len()];
+        while let Some(pc) = stack.pop() {
+            if !std::mem::replace(&mut terminating[pc], true) {
+                stack.extend(&predecessors[pc]);
+            }
+        }
+        terminating
+    }
+
+    /// The accumulator after running from the position to termination, adding to the given one,
+    /// or `None` if it overflows.
+    fn accumulate_from(
+        program: &[Instruction],
+        mut pc: usize,
+        mut accumulator: isize,
+    ) -> Option<isize> {
+        while let Some(instruction) = program.get(pc) {
+            if instruction.operation == Operation::Acc {
+                accumulator = accumulator.checked_add(instruction.argument)?;
+            }
+            pc = match successor(program, instruction, pc) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        Some(accumulator)
+    }
+
+    /// Finds the instruction to flip in linear time, without running any modified programs.
+    ///
+    /// Only instructions on the original loop are executed, so the flip must be the first one
+    /// there that leads to an instruction running to termination. Programs that terminate
+    /// already need no repair, and programs whose accumulator overflows have none.
+    pub fn find_repair(program: &[Instruction]) -> Option<Repair> {
+        let terminating = terminating(program);
+        if terminating.first().copied().unwrap_or(true) {
+            return None;
+        }
+        let mut visited = vec![false; program.len()];
+        let mut accumulator = 0;
+        let mut pc = 0;
+        while pc < program.len() && !std::mem::replace(&mut visited[pc], true) {
+            let instruction = &program[pc];
+            if let Some(flipped) = instruction.flipped() {
+                match successor(program, &flipped, pc) {
+                    Some(next) if next == program.len() || terminating[next] => {
+                        return Some(Repair {
+                            index: pc,
+                            accumulator: accumulate_from(program, next, accumulator)?,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            if instruction.operation == Operation::Acc {
+                accumulator = accumulator.checked_add(instruction.argument)?;
+            }
+            pc = successor(program, instruction, pc)?;
+        }
+        None
+    }
+
+    #[aoc(day8, part2, reverse)]
+    fn repair(program: &[Instruction]) -> Option<isize> {
+        find_repair(program).map(|repair| repair.accumulator)
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Operation {
         Nop,
         Acc,
         Jmp,
     }
 
-    #[derive(Copy, Clone, Debug)]
-    struct Instruction {
-        operation: Operation,
-        argument: isize,
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct Instruction {
+        pub operation: Operation,
+        pub argument: isize,
+    }
+
+    impl Operation {
+        /// The operation that a corrupted jmp or nop should have been.
+        pub fn flipped(self) -> Option<Operation> {
+            match self {
+                Operation::Nop => Some(Operation::Jmp),
+                Operation::Jmp => Some(Operation::Nop),
+                Operation::Acc => None,
+            }
+        }
+    }
+
+    impl Instruction {
+        /// The position to continue at after this instruction at the given position, which may
+        /// be outside the program, or `None` if it does not fit into an `isize`.
+        pub fn target(&self, pc: usize) -> Option<isize> {
+            match self.operation {
+                Operation::Jmp => (pc as isize).checked_add(self.argument),
+                Operation::Acc | Operation::Nop => (pc as isize).checked_add(1),
+            }
+        }
+
+        pub fn flipped(&self) -> Option<Instruction> {
+            self.operation.flipped().map(|operation| Instruction {
+                operation,
+                argument: self.argument,
+            })
+        }
+    }
+
+    impl Execute for Instruction {
+        fn execute(&self, machine: &mut Machine) -> Result<Flow, Fault> {
+            match self.operation {
+                Operation::Nop => Ok(Flow::Next),
+                Operation::Acc => {
+                    machine.accumulator = machine
+                        .accumulator
+                        .checked_add(self.argument)
+                        .ok_or(Fault::Overflow)?;
+                    Ok(Flow::Next)
+                }
+                Operation::Jmp => Ok(Flow::Jump(self.argument)),
+            }
+        }
     }
 
     impl FromStr for Instruction {
@@ -223,51 +683,1545 @@ mod parsed {
                 .collect_tuple()
                 .ok_or(ParseError::FormatError)?;
             let argument = argument.parse::<isize>().map_err(ParseError::from)?;
-            let operation = match operation {
-                "acc" => Operation::Acc,
-                "jmp" => Operation::Jmp,
-                "nop" => Operation::Nop,
-                _ => return Err(ProgramError::UnknownOperation(operation.to_string())),
-            };
             Ok(Instruction {
-                operation,
+                operation: operation.parse()?,
                 argument,
             })
         }
     }
 
-    #[cfg(test)]
-    mod should {
-        use super::*;
+    impl FromStr for Operation {
+        type Err = ProgramError;
 
-        #[test]
-        fn have_accumulator_of_5_after_first_iteration() {
-            assert_eq!(
-                read_program(EXAMPLE).map(|program| execute_until_loop(&program)),
-                Ok(Some(5))
-            );
+        fn from_str(input: &str) -> Result<Self, Self::Err> {
+            match input {
+                "acc" => Ok(Operation::Acc),
+                "jmp" => Ok(Operation::Jmp),
+                "nop" => Ok(Operation::Nop),
+                _ => Err(ProgramError::UnknownOperation(input.to_string())),
+            }
         }
+    }
 
-        #[test]
-        fn solve_part1() {
-            assert_eq!(
-                read_program(INPUT).map(|program| execute_until_loop(&program)),
-                Ok(Some(1801))
-            );
+    impl fmt::Display for Operation {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(match self {
+                Operation::Nop => "nop",
+                Operation::Acc => "acc",
+                Operation::Jmp => "jmp",
+            })
         }
+    }
 
-        #[test]
-        fn finds_fix_for_example() {
-            assert_eq!(
-                read_program(EXAMPLE).map(|program| find_termination_fix(&program)),
-                Ok(Some(8))
+    impl fmt::Display for Instruction {
+        /// Writes the instruction in canonical form, like `acc +1`.
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} {:+}", self.operation, self.argument)
+        }
+    }
+
+    /// Rewrites the program in canonical form, with one space and a signed argument per line.
+    pub fn format_program(input: &str) -> Result<String, ProgramError> {
+        Ok(read_lines::<Instruction>(input)?
+            .iter()
+            .map(|instruction| format!("{}\n", instruction))
+            .collect())
+    }
+
+    /// Lists the program with positions, labels for all jump targets and the absolute target
+    /// of every jump.
+    pub fn disassemble(program: &[Instruction]) -> String {
+        let targets: BTreeSet<usize> = program
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| instruction.operation == Operation::Jmp)
+            .filter_map(|(pc, instruction)| successor(program, instruction, pc))
+            .collect();
+        let labels: BTreeMap<usize, String> = targets
+            .into_iter()
+            .enumerate()
+            .map(|(index, target)| (target, format!("l{}", index)))
+            .collect();
+        let position_width = program.len().to_string().len();
+        let label_width = labels
+            .values()
+            .map(|label| label.len() + 1)
+            .max()
+            .unwrap_or(0);
+        let label = |pc: usize| {
+            labels
+                .get(&pc)
+                .map(|label| format!("{}:", label))
+                .unwrap_or_default()
+        };
+        let mut listing = String::new();
+        for (pc, instruction) in program.iter().enumerate() {
+            let mut line = format!(
+                "{:>position_width$}  {:<label_width$}  {}",
+                pc,
+                label(pc),
+                instruction,
+                position_width = position_width,
+                label_width = label_width
             );
+            if instruction.operation == Operation::Jmp {
+                line += &match (successor(program, instruction, pc), instruction.target(pc)) {
+                    (Some(target), _) => format!("  ; -> {} ({})", target, labels[&target]),
+                    (None, Some(to)) => format!("  ; -> {} (out of bounds)", to),
+                    (None, None) => "  ; -> overflow (out of bounds)".to_string(),
+                };
+            }
+            listing += line.trim_end();
+            listing.push('\n');
         }
-        #[test]
-        fn solve_part2() {
-            assert_eq!(
-                read_program(INPUT).map(|program| find_termination_fix(&program)),
-                Ok(Some(2060))
+        if labels.contains_key(&program.len()) {
+            listing += &format!(
+                "{:>position_width$}  {}\n",
+                program.len(),
+                label(program.len()),
+                position_width = position_width
+            );
+        }
+        listing
+    }
+
+    /// Splits a leading `label:` off the line.
+    fn split_label(line: &str) -> Option<(&str, &str)> {
+        let (label, rest) = line.split_once(':')?;
+        Some((label, rest.trim_start())).filter(|_| is_label(label))
+    }
+
+    fn is_label(name: &str) -> bool {
+        name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    /// Assembles a program in which jmp and nop may refer to labels instead of offsets.
+    /// Lines may start with `label:` and comments start with `;`.
+    pub fn assemble(source: &str) -> Result<Vec<Instruction>, ProgramError> {
+        let mut labels = HashMap::new();
+        let mut lines = Vec::new();
+        for line in source.lines() {
+            let mut line = line.split(';').next().unwrap_or_default().trim();
+            while let Some((label, rest)) = split_label(line) {
+                if labels.insert(label, lines.len()).is_some() {
+                    return Err(ProgramError::DuplicateLabel(label.to_string()));
+                }
+                line = rest;
+            }
+            if !line.is_empty() {
+                lines.push(line);
+            }
+        }
+        lines
+            .iter()
+            .enumerate()
+            .map(|(pc, line)| {
+                let (operation, argument) = line
+                    .split_whitespace()
+                    .collect_tuple()
+                    .ok_or(ParseError::FormatError)?;
+                if !is_label(argument) {
+                    return line.parse();
+                }
+                let operation = operation.parse()?;
+                if operation == Operation::Acc {
+                    return Err(ParseError::FormatError.into());
+                }
+                let target = labels
+                    .get(argument)
+                    .ok_or_else(|| ProgramError::UnknownLabel(argument.to_string()))?;
+                Ok(Instruction {
+                    operation,
+                    argument: *target as isize - pc as isize,
+                })
+            })
+            .collect()
+    }
+
+    /// An executed instruction, with the accumulator after executing it.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct TraceEntry<I = Instruction> {
+        pub pc: usize,
+        pub instruction: I,
+        pub accumulator: isize,
+    }
+
+    /// Why [`Vm::run`] or [`Vm::run_until`] returned.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Stop {
+        /// The program counter left the program.
+        Terminated,
+        /// The next instruction has been executed before.
+        LoopDetected,
+        /// The next instruction is at a breakpoint.
+        Breakpoint(usize),
+        /// The last instruction changed the accumulator to a watched value.
+        Watch(isize),
+        /// The condition given to [`Vm::run_until`] holds.
+        Condition,
+        /// The next instruction jumps out of bounds.
+        JumpOutOfBounds { from: usize, to: isize },
+        /// The step limit of the [`RunOptions`] has been reached.
+        StepLimitExceeded,
+        /// The next instruction cannot be executed.
+        Fault(Fault),
+    }
+
+    /// A debugger for the handheld, executing a program one instruction at a time.
+    ///
+    /// Runs the original instructions by default, and any other [`Execute`] implementation,
+    /// like [`extended::Instruction`](super::extended::Instruction).
+    #[derive(Clone, Debug)]
+    pub struct Vm<'a, I = Instruction> {
+        program: &'a [I],
+        pc: usize,
+        machine: Machine,
+        executed: Vec<bool>,
+        breakpoints: BTreeSet<usize>,
+        watches: BTreeSet<isize>,
+        trace: Vec<TraceEntry<I>>,
+        options: RunOptions,
+        /// The breakpoint the last run stopped at, which the next run executes without stopping.
+        resume_from: Option<usize>,
+    }
+
+    impl<'a, I: Execute + Clone> Vm<'a, I> {
+        pub fn new(program: &'a [I]) -> Self {
+            Vm::with_options(program, RunOptions::default())
+        }
+
+        pub fn with_options(program: &'a [I], options: RunOptions) -> Self {
+            Vm {
+                options,
+                program,
+                pc: 0,
+                machine: Machine::default(),
+                executed: vec![false; program.len()],
+                breakpoints: BTreeSet::new(),
+                watches: BTreeSet::new(),
+                trace: Vec::new(),
+                resume_from: None,
+            }
+        }
+
+        pub fn program(&self) -> &'a [I] {
+            self.program
+        }
+
+        pub fn pc(&self) -> usize {
+            self.pc
+        }
+
+        pub fn accumulator(&self) -> isize {
+            self.machine.accumulator
+        }
+
+        pub fn machine(&self) -> &Machine {
+            &self.machine
+        }
+
+        /// The number of instructions executed so far.
+        pub fn steps(&self) -> usize {
+            self.trace.len()
+        }
+
+        /// Every instruction executed so far, in order.
+        pub fn trace(&self) -> &[TraceEntry<I>] {
+            &self.trace
+        }
+
+        pub fn is_terminated(&self) -> bool {
+            self.pc >= self.program.len()
+        }
+
+        pub fn has_executed(&self, pc: usize) -> bool {
+            self.executed.get(pc).copied().unwrap_or(false)
+        }
+
+        /// The instruction that the next step executes, unless the program terminated.
+        pub fn next_instruction(&self) -> Option<&'a I> {
+            self.program.get(self.pc)
+        }
+
+        /// Adds a breakpoint, returning whether it is new.
+        pub fn add_breakpoint(&mut self, pc: usize) -> bool {
+            self.breakpoints.insert(pc)
+        }
+
+        /// Removes a breakpoint, returning whether it was set.
+        pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+            self.breakpoints.remove(&pc)
+        }
+
+        pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+            self.breakpoints.iter().copied()
+        }
+
+        /// Watches for the accumulator changing to the value, returning whether the watch is new.
+        pub fn add_watch(&mut self, accumulator: isize) -> bool {
+            self.watches.insert(accumulator)
+        }
+
+        /// Removes a watch, returning whether it was set.
+        pub fn remove_watch(&mut self, accumulator: isize) -> bool {
+            self.watches.remove(&accumulator)
+        }
+
+        pub fn watches(&self) -> impl Iterator<Item = isize> + '_ {
+            self.watches.iter().copied()
+        }
+
+        /// Restarts the program, keeping breakpoints and watches.
+        pub fn reset(&mut self) {
+            self.pc = 0;
+            self.machine = Machine::default();
+            self.executed
+                .iter_mut()
+                .for_each(|executed| *executed = false);
+            self.trace.clear();
+            self.resume_from = None;
+        }
+
+        /// The machine after the next instruction and the position to continue at, or why the
+        /// next instruction cannot be executed.
+        fn attempt(&self) -> Result<(Machine, usize), Stop> {
+            let instruction = self.next_instruction().ok_or(Stop::Terminated)?;
+            let mut machine = self.machine;
+            let offset = match instruction.execute(&mut machine).map_err(Stop::Fault)? {
+                Flow::Next => 1,
+                Flow::Jump(offset) => offset,
+                Flow::Halt => return Ok((machine, self.program.len())),
+            };
+            let to = (self.pc as isize)
+                .checked_add(offset)
+                .ok_or(Stop::Fault(Fault::Overflow))?;
+            match self.options.landing(to, self.program.len()) {
+                Some(next) => Ok((machine, next)),
+                None => Err(Stop::JumpOutOfBounds { from: self.pc, to }),
+            }
+        }
+
+        /// Why the next step cannot execute: the program terminated, or the next instruction
+        /// jumps out of bounds or faults.
+        pub fn blocked(&self) -> Option<Stop> {
+            self.attempt().err()
+        }
+
+        fn advance(&mut self, machine: Machine, next: usize) -> TraceEntry<I> {
+            let pc = self.pc;
+            self.resume_from = None;
+            self.machine = machine;
+            self.pc = next;
+            self.executed[pc] = true;
+            let entry = TraceEntry {
+                pc,
+                instruction: self.program[pc].clone(),
+                accumulator: self.machine.accumulator,
+            };
+            self.trace.push(entry.clone());
+            entry
+        }
+
+        /// Executes the next instruction, unless [`Vm::blocked`] tells why it cannot.
+        pub fn step(&mut self) -> Option<TraceEntry<I>> {
+            let (machine, next) = self.attempt().ok()?;
+            Some(self.advance(machine, next))
+        }
+
+        /// Executes instructions until the condition holds after a step, a breakpoint or watch
+        /// is hit, the program ends or it would execute an instruction a second time.
+        ///
+        /// Breakpoints stop before their instruction, except the one the last run stopped at.
+        pub fn run_until(&mut self, mut condition: impl FnMut(&Self) -> bool) -> Stop {
+            let mut resume_from = self.resume_from.take();
+            loop {
+                if self.is_terminated() {
+                    return Stop::Terminated;
+                }
+                if self.breakpoints.contains(&self.pc) && resume_from.take() != Some(self.pc) {
+                    self.resume_from = Some(self.pc);
+                    return Stop::Breakpoint(self.pc);
+                }
+                if self.options.detect_loops && self.has_executed(self.pc) {
+                    return Stop::LoopDetected;
+                }
+                if self.options.exceeds_step_limit(self.steps()) {
+                    return Stop::StepLimitExceeded;
+                }
+                let (machine, next) = match self.attempt() {
+                    Ok(step) => step,
+                    Err(stop) => return stop,
+                };
+                let previous = self.accumulator();
+                self.advance(machine, next);
+                resume_from = None;
+                if self.accumulator() != previous && self.watches.contains(&self.accumulator()) {
+                    return Stop::Watch(self.accumulator());
+                }
+                if condition(self) {
+                    return Stop::Condition;
+                }
+            }
+        }
+
+        /// Executes instructions until a breakpoint or watch is hit, the program ends or it
+        /// would execute an instruction a second time.
+        pub fn run(&mut self) -> Stop {
+            self.run_until(|_| false)
+        }
+    }
+
+    #[cfg(test)]
+    mod should {
+        use super::*;
+
+        #[test]
+        fn have_accumulator_of_5_after_first_iteration() {
+            assert_eq!(
+                read_program(EXAMPLE).map(|program| execute_until_loop(&program)),
+                Ok(Some(5))
+            );
+        }
+
+        #[test]
+        fn solve_part1() {
+            assert_eq!(
+                read_program(INPUT).map(|program| execute_until_loop(&program)),
+                Ok(Some(1801))
+            );
+        }
+
+        #[test]
+        fn finds_fix_for_example() {
+            assert_eq!(
+                read_program(EXAMPLE).map(|program| find_termination_fix(&program)),
+                Ok(Some(8))
+            );
+        }
+        #[test]
+        fn solve_part2() {
+            assert_eq!(
+                read_program(INPUT).map(|program| find_termination_fix(&program)),
+                Ok(Some(2060))
+            );
+        }
+
+        #[test]
+        fn steps_through_example() {
+            let program = read_program(EXAMPLE).expect("Failed to read example");
+            let mut vm = Vm::new(&program);
+            assert_eq!(
+                vm.next_instruction(),
+                Some(&Instruction {
+                    operation: Operation::Nop,
+                    argument: 0
+                })
+            );
+            vm.step();
+            let entry = vm.step().expect("Terminated early");
+            assert_eq!((entry.pc, entry.accumulator), (1, 1));
+            assert_eq!(entry.instruction.operation, Operation::Acc);
+            assert_eq!((vm.pc(), vm.accumulator(), vm.steps()), (2, 1, 2));
+            assert!(vm.has_executed(1));
+            assert!(!vm.has_executed(2));
+        }
+
+        #[test]
+        fn records_trace_until_loop() {
+            let program = read_program(EXAMPLE).expect("Failed to read example");
+            let mut vm = Vm::new(&program);
+            assert_eq!(vm.run(), Stop::LoopDetected);
+            assert_eq!(
+                vm.trace()
+                    .iter()
+                    .map(|entry| (entry.pc, entry.accumulator))
+                    .collect_vec(),
+                vec![(0, 0), (1, 1), (2, 1), (6, 2), (7, 2), (3, 5), (4, 5)]
+            );
+            assert_eq!((vm.pc(), vm.accumulator()), (1, 5));
+        }
+
+        #[test]
+        fn stops_at_breakpoints_and_watches() {
+            let program = read_program(EXAMPLE).expect("Failed to read example");
+            let mut vm = Vm::new(&program);
+            assert!(vm.add_breakpoint(7));
+            assert!(vm.add_watch(5));
+            assert_eq!(vm.run(), Stop::Breakpoint(7));
+            assert_eq!(vm.accumulator(), 2);
+            assert_eq!(vm.run(), Stop::Watch(5));
+            assert_eq!(vm.pc(), 4);
+            assert!(vm.remove_breakpoint(7));
+            assert_eq!(vm.breakpoints().count(), 0);
+            assert_eq!(vm.run(), Stop::LoopDetected);
+            vm.reset();
+            assert_eq!((vm.pc(), vm.accumulator(), vm.steps()), (0, 0, 0));
+            assert_eq!(vm.run_until(|vm| vm.pc() == 6), Stop::Condition);
+            assert_eq!(vm.steps(), 3);
+        }
+
+        #[test]
+        fn stops_at_breakpoints_before_executing_them() {
+            let program = read_program(EXAMPLE).expect("Failed to read example");
+            let mut vm = Vm::new(&program);
+            assert!(vm.add_breakpoint(0));
+            assert_eq!(vm.run(), Stop::Breakpoint(0));
+            assert_eq!(vm.steps(), 0);
+            assert_eq!(vm.run(), Stop::LoopDetected);
+            vm.reset();
+            assert_eq!(vm.run(), Stop::Breakpoint(0));
+            assert!(vm.add_breakpoint(2));
+            assert_eq!(vm.run(), Stop::Breakpoint(2));
+            assert_eq!(vm.run(), Stop::LoopDetected);
+        }
+
+        #[test]
+        fn runs_fixed_program_to_termination() {
+            let mut program = read_program(EXAMPLE).expect("Failed to read example");
+            program[7].operation = Operation::Nop;
+            let mut vm = Vm::new(&program);
+            assert_eq!(vm.run(), Stop::Terminated);
+            assert_eq!(vm.accumulator(), 8);
+            assert_eq!(vm.next_instruction(), None);
+            assert_eq!(vm.step(), None);
+        }
+
+        #[test]
+        fn finds_repair_for_example() {
+            let program = read_program(EXAMPLE).expect("Failed to read example");
+            assert_eq!(
+                find_repair(&program),
+                Some(Repair {
+                    index: 7,
+                    accumulator: 8
+                })
+            );
+        }
+
+        #[test]
+        fn finds_repair_in_linear_time() {
+            let program = read_program(INPUT).expect("Failed to read input");
+            let repair = find_repair(&program).expect("No repair found");
+            assert_eq!(repair.accumulator, 2060);
+            let mut fixed = program.clone();
+            fixed[repair.index] = program[repair.index].flipped().expect("Flipped acc");
+            assert_eq!(
+                run_first_iteration(&fixed),
+                Outcome::Terminated { acc: 2060 }
+            );
+        }
+
+        #[test]
+        fn repairs_jump_off_the_end() {
+            let program = read_lines::<Instruction>("acc +2\nnop +2\njmp -2")
+                .expect("Failed to read program");
+            assert_eq!(
+                find_repair(&program),
+                Some(Repair {
+                    index: 1,
+                    accumulator: 2
+                })
+            );
+            assert_eq!(
+                read_lines::<Instruction>("jmp +0").map(|program| find_repair(&program)),
+                Ok(Some(Repair {
+                    index: 0,
+                    accumulator: 0
+                }))
+            );
+        }
+
+        #[test]
+        fn finds_no_repair_for_terminating_program() {
+            let program =
+                read_lines::<Instruction>("acc +1\nnop +0").expect("Failed to read program");
+            assert_eq!(find_repair(&program), None);
+        }
+
+        #[test]
+        fn stops_before_jumping_out_of_bounds() {
+            let program =
+                read_lines::<Instruction>("acc +1\njmp -2").expect("Failed to read program");
+            let mut vm = Vm::new(&program);
+            assert_eq!(vm.run(), Stop::JumpOutOfBounds { from: 1, to: -1 });
+            assert_eq!((vm.pc(), vm.accumulator()), (1, 1));
+            assert_eq!(vm.step(), None);
+            assert_eq!(
+                vm.blocked(),
+                Some(Stop::JumpOutOfBounds { from: 1, to: -1 })
+            );
+            assert_eq!(
+                execute(&program, RunOptions::default()),
+                Outcome::JumpOutOfBounds { from: 1, to: -1 }
+            );
+        }
+
+        #[test]
+        fn faults_on_overflow() {
+            let program = read_lines::<Instruction>("acc +9223372036854775807\nacc +2")
+                .expect("Failed to read program");
+            let mut vm = Vm::new(&program);
+            assert_eq!(vm.run(), Stop::Fault(Fault::Overflow));
+            assert_eq!((vm.pc(), vm.accumulator()), (1, isize::MAX));
+            assert_eq!(
+                execute(&program, RunOptions::default()),
+                Outcome::Faulted {
+                    pc: 1,
+                    fault: Fault::Overflow
+                }
+            );
+            let program = read_lines::<Instruction>("nop +0\njmp +9223372036854775807")
+                .expect("Failed to read program");
+            assert_eq!(
+                execute(&program, RunOptions::default()),
+                Outcome::Faulted {
+                    pc: 1,
+                    fault: Fault::Overflow
+                }
+            );
+        }
+
+        #[test]
+        fn repairs_around_overflowing_jumps() {
+            let program = read_lines::<Instruction>("nop +0\njmp +9223372036854775807")
+                .expect("Failed to read program");
+            let repair = Repair {
+                index: 1,
+                accumulator: 0,
+            };
+            assert_eq!(find_repair(&program), Some(repair));
+            assert_eq!(unique_fix(&program), Ok(repair));
+        }
+
+        #[test]
+        fn finds_no_repair_when_the_accumulator_overflows() {
+            let program = read_lines::<Instruction>("acc +9223372036854775807\nacc +1\njmp -2")
+                .expect("Failed to read program");
+            assert_eq!(find_repair(&program), None);
+            assert_eq!(unique_fix(&program), Err(ProgramError::NoFix));
+            assert_eq!(
+                fix_candidates(&program)
+                    .iter()
+                    .map(|candidate| (candidate.index, candidate.result))
+                    .collect_vec(),
+                vec![(
+                    2,
+                    FixResult::Ran(Outcome::Faulted {
+                        pc: 1,
+                        fault: Fault::Overflow
+                    })
+                )]
+            );
+        }
+
+        #[test]
+        fn terminates_past_the_end_only_when_lenient() {
+            let program =
+                read_lines::<Instruction>("acc +1\njmp +2").expect("Failed to read program");
+            assert_eq!(
+                execute(&program, RunOptions::default()),
+                Outcome::JumpOutOfBounds { from: 1, to: 3 }
+            );
+            let lenient = RunOptions {
+                termination: Termination::Lenient,
+                ..RunOptions::default()
+            };
+            assert_eq!(execute(&program, lenient), Outcome::Terminated { acc: 1 });
+        }
+
+        #[test]
+        fn reports_loops_and_step_limits() {
+            let program = read_program(EXAMPLE).expect("Failed to read example");
+            assert_eq!(
+                execute(&program, RunOptions::default()),
+                Outcome::LoopDetected { pc: 1, acc: 5 }
+            );
+            let limited = RunOptions {
+                step_limit: Some(3),
+                ..RunOptions::default()
+            };
+            assert_eq!(
+                execute(&program, limited),
+                Outcome::StepLimitExceeded { pc: 6, acc: 1 }
+            );
+        }
+
+        #[test]
+        fn displays_canonical_instructions() {
+            let program = read_program(EXAMPLE).expect("Failed to read example");
+            assert_eq!(program.iter().join("\n"), EXAMPLE);
+        }
+
+        #[test]
+        fn formats_programs() {
+            assert_eq!(
+                format_program("nop 0\n  acc   +1\njmp\t-4"),
+                Ok("nop +0\nacc +1\njmp -4\n".to_string())
+            );
+            assert_eq!(format_program(INPUT), Ok(INPUT.to_string()));
+            assert_eq!(
+                format_program("mov +1"),
+                Err(ProgramError::UnknownOperation("mov".to_string()))
+            );
+        }
+
+        #[test]
+        fn disassembles_with_labels_and_targets() {
+            let program = read_program(EXAMPLE).expect("Failed to read example");
+            assert_eq!(
+                disassemble(&program),
+                "\
+0       nop +0
+1  l0:  acc +1
+2       jmp +4  ; -> 6 (l2)
+3  l1:  acc +3
+4       jmp -3  ; -> 1 (l0)
+5       acc -99
+6  l2:  acc +1
+7       jmp -4  ; -> 3 (l1)
+8       acc +6
+"
+            );
+        }
+
+        #[test]
+        fn disassembles_jumps_to_the_end_and_out_of_bounds() {
+            let program =
+                read_lines::<Instruction>("jmp +2\njmp -2").expect("Failed to read program");
+            assert_eq!(
+                disassemble(&program),
+                "\
+0       jmp +2  ; -> 2 (l0)
+1       jmp -2  ; -> -1 (out of bounds)
+2  l0:
+"
+            );
+            let program = read_lines::<Instruction>("nop +0\njmp +9223372036854775807")
+                .expect("Failed to read program");
+            assert_eq!(
+                disassemble(&program),
+                "\
+0    nop +0
+1    jmp +9223372036854775807  ; -> overflow (out of bounds)
+"
+            );
+        }
+
+        #[test]
+        fn assembles_labels_into_offsets() {
+            let source = "\
+        nop +0
+start:  acc +1
+        jmp skip  ; over the next instruction
+loop:   acc +3
+        jmp start
+        acc -99
+skip:   acc +1
+        jmp loop
+        acc +6";
+            let program = assemble(source).expect("Failed to assemble");
+            assert_eq!(program.iter().join("\n"), EXAMPLE);
+            let program = assemble("nop end\nacc +1\nend:").expect("Failed to assemble");
+            assert_eq!(program.iter().join("\n"), "nop +2\nacc +1");
+        }
+
+        #[test]
+        fn reports_label_errors() {
+            assert_eq!(
+                assemble("jmp nowhere"),
+                Err(ProgramError::UnknownLabel("nowhere".to_string()))
+            );
+            assert_eq!(
+                assemble("a: nop +0\na: jmp a"),
+                Err(ProgramError::DuplicateLabel("a".to_string()))
+            );
+            assert_eq!(
+                assemble("a: acc a"),
+                Err(ProgramError::ParseError(ParseError::FormatError))
+            );
+        }
+
+        #[test]
+        fn lists_fix_candidates_for_example() {
+            let program = read_program(EXAMPLE).expect("Failed to read example");
+            assert_eq!(
+                fix_candidates(&program)
+                    .iter()
+                    .map(|candidate| (candidate.index, candidate.result))
+                    .collect_vec(),
+                vec![
+                    (0, FixResult::Skipped(SkipReason::JumpsToItself)),
+                    (2, FixResult::Ran(Outcome::LoopDetected { pc: 1, acc: 4 })),
+                    (4, FixResult::Ran(Outcome::LoopDetected { pc: 6, acc: -94 })),
+                    (7, FixResult::Ran(Outcome::Terminated { acc: 8 })),
+                ]
+            );
+        }
+
+        #[test]
+        fn lists_skipped_and_out_of_bounds_candidates() {
+            let program = read_lines::<Instruction>("nop -1\njmp +1\nnop +1\nacc +3\njmp -2")
+                .expect("Failed to read program");
+            let candidates = fix_candidates(&program);
+            assert_eq!(
+                candidates
+                    .iter()
+                    .map(|candidate| (candidate.index, candidate.result))
+                    .collect_vec(),
+                vec![
+                    (
+                        0,
+                        FixResult::Ran(Outcome::JumpOutOfBounds { from: 0, to: -1 })
+                    ),
+                    (1, FixResult::Skipped(SkipReason::ChangesNothing)),
+                    (2, FixResult::Skipped(SkipReason::ChangesNothing)),
+                    (4, FixResult::Ran(Outcome::Terminated { acc: 3 })),
+                ]
+            );
+            assert_eq!(candidates[1].instruction.to_string(), "jmp +1");
+        }
+
+        #[test]
+        fn confirms_unique_fix() {
+            let program = read_program(INPUT).expect("Failed to read input");
+            let fix = unique_fix(&program).expect("Fix is not unique");
+            assert_eq!(fix.accumulator, 2060);
+            assert_eq!(find_repair(&program), Some(fix));
+        }
+
+        #[test]
+        fn reports_missing_and_ambiguous_fixes() {
+            assert_eq!(
+                read_lines::<Instruction>("jmp +0\njmp -1").map(|program| unique_fix(&program)),
+                Ok(Err(ProgramError::NoFix))
+            );
+            assert_eq!(
+                read_lines::<Instruction>("nop +2\njmp +0").map(|program| unique_fix(&program)),
+                Ok(Err(ProgramError::AmbiguousFix(vec![0, 1])))
+            );
+        }
+
+        #[test]
+        fn does_not_repair_into_jumps_out_of_bounds() {
+            let program = read_lines::<Instruction>("nop +4\njmp +0\njmp -1")
+                .expect("Failed to read program");
+            assert_eq!(find_repair(&program), None);
+        }
+    }
+}
+
+/// A handheld with an opcode table, so that new instructions only need to be registered
+/// with the [`InstructionSet`] used for parsing.
+pub mod extended {
+    use super::*;
+
+    /// A register from `a` to `z`, or an immediate number.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Operand {
+        Register(u8),
+        Immediate(isize),
+    }
+
+    impl FromStr for Operand {
+        type Err = ParseError;
+
+        fn from_str(input: &str) -> Result<Self, Self::Err> {
+            match input.as_bytes() {
+                [register @ b'a'..=b'z'] => Ok(Operand::Register(register - b'a')),
+                _ => Ok(Operand::Immediate(input.parse()?)),
+            }
+        }
+    }
+
+    /// What an operand of an opcode accepts.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum OperandKind {
+        /// A register that the opcode writes to.
+        Register,
+        /// A register or immediate number that the opcode reads.
+        Value,
+    }
+
+    impl Machine {
+        pub fn value(&self, operand: Operand) -> isize {
+            match operand {
+                Operand::Register(register) => self.registers[register as usize],
+                Operand::Immediate(value) => value,
+            }
+        }
+
+        /// The register to write to, failing for immediates.
+        pub fn register_mut(&mut self, operand: Operand) -> Result<&mut isize, Fault> {
+            match operand {
+                Operand::Register(register) => Ok(&mut self.registers[register as usize]),
+                Operand::Immediate(_) => Err(Fault::NotARegister),
+            }
+        }
+    }
+
+    #[derive(Copy, Clone, Debug)]
+    pub struct Opcode {
+        pub mnemonic: &'static str,
+        pub operands: &'static [OperandKind],
+        pub execute: fn(&mut Machine, &[Operand]) -> Result<Flow, Fault>,
+    }
+
+    /// Opcodes by mnemonic.
+    #[derive(Clone, Debug, Default)]
+    pub struct InstructionSet {
+        opcodes: HashMap<&'static str, Opcode>,
+    }
+
+    impl InstructionSet {
+        /// The original `nop`, `acc` and `jmp` instructions.
+        pub fn handheld() -> Self {
+            let mut instructions = InstructionSet::default();
+            instructions.register(Opcode {
+                mnemonic: "nop",
+                operands: &[OperandKind::Value],
+                execute: |_, _| Ok(Flow::Next),
+            });
+            instructions.register(Opcode {
+                mnemonic: "acc",
+                operands: &[OperandKind::Value],
+                execute: |machine, operands| {
+                    machine.accumulator = machine
+                        .accumulator
+                        .checked_add(machine.value(operands[0]))
+                        .ok_or(Fault::Overflow)?;
+                    Ok(Flow::Next)
+                },
+            });
+            instructions.register(Opcode {
+                mnemonic: "jmp",
+                operands: &[OperandKind::Value],
+                execute: |machine, operands| Ok(Flow::Jump(machine.value(operands[0]))),
+            });
+            instructions
+        }
+
+        /// The handheld instructions, together with
+        /// * `mul x`, multiplying the accumulator,
+        /// * `set r x` and `add r x`, writing to registers,
+        /// * `jz x y` and `jnz x y`, jumping by `y` if `x` is zero or not,
+        /// * `halt`, terminating the program.
+        pub fn extended() -> Self {
+            let mut instructions = InstructionSet::handheld();
+            instructions.register(Opcode {
+                mnemonic: "mul",
+                operands: &[OperandKind::Value],
+                execute: |machine, operands| {
+                    machine.accumulator = machine
+                        .accumulator
+                        .checked_mul(machine.value(operands[0]))
+                        .ok_or(Fault::Overflow)?;
+                    Ok(Flow::Next)
+                },
+            });
+            instructions.register(Opcode {
+                mnemonic: "set",
+                operands: &[OperandKind::Register, OperandKind::Value],
+                execute: |machine, operands| {
+                    let value = machine.value(operands[1]);
+                    *machine.register_mut(operands[0])? = value;
+                    Ok(Flow::Next)
+                },
+            });
+            instructions.register(Opcode {
+                mnemonic: "add",
+                operands: &[OperandKind::Register, OperandKind::Value],
+                execute: |machine, operands| {
+                    let value = machine.value(operands[1]);
+                    let register = machine.register_mut(operands[0])?;
+                    *register = register.checked_add(value).ok_or(Fault::Overflow)?;
+                    Ok(Flow::Next)
+                },
+            });
+            instructions.register(Opcode {
+                mnemonic: "jz",
+                operands: &[OperandKind::Value, OperandKind::Value],
+                execute: |machine, operands| match machine.value(operands[0]) {
+                    0 => Ok(Flow::Jump(machine.value(operands[1]))),
+                    _ => Ok(Flow::Next),
+                },
+            });
+            instructions.register(Opcode {
+                mnemonic: "jnz",
+                operands: &[OperandKind::Value, OperandKind::Value],
+                execute: |machine, operands| match machine.value(operands[0]) {
+                    0 => Ok(Flow::Next),
+                    _ => Ok(Flow::Jump(machine.value(operands[1]))),
+                },
+            });
+            instructions.register(Opcode {
+                mnemonic: "halt",
+                operands: &[],
+                execute: |_, _| Ok(Flow::Halt),
+            });
+            instructions
+        }
+
+        /// Adds the opcode, returning the one it replaces.
+        pub fn register(&mut self, opcode: Opcode) -> Option<Opcode> {
+            self.opcodes.insert(opcode.mnemonic, opcode)
+        }
+
+        pub fn opcode(&self, mnemonic: &str) -> Option<&Opcode> {
+            self.opcodes.get(mnemonic)
+        }
+
+        pub fn parse(&self, line: &str) -> Result<Instruction, ProgramError> {
+            let mut words = line.split_whitespace();
+            let mnemonic = words.next().ok_or(ParseError::FormatError)?;
+            let opcode = *self
+                .opcode(mnemonic)
+                .ok_or_else(|| ProgramError::UnknownOperation(mnemonic.to_string()))?;
+            let operands = words
+                .map(|word| word.parse::<Operand>())
+                .collect::<Result<Vec<_>, _>>()?;
+            if operands.len() != opcode.operands.len()
+                || opcode
+                    .operands
+                    .iter()
+                    .zip(&operands)
+                    .any(|(kind, operand)| {
+                        *kind == OperandKind::Register && !matches!(operand, Operand::Register(_))
+                    })
+            {
+                return Err(ParseError::FormatError.into());
+            }
+            Ok(Instruction { opcode, operands })
+        }
+
+        pub fn read_program(&self, input: &str) -> Result<Vec<Instruction>, ProgramError> {
+            input.lines().map(|line| self.parse(line)).collect()
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct Instruction {
+        pub opcode: Opcode,
+        pub operands: Vec<Operand>,
+    }
+
+    impl Execute for Instruction {
+        fn execute(&self, machine: &mut Machine) -> Result<Flow, Fault> {
+            (self.opcode.execute)(machine, &self.operands)
+        }
+    }
+
+    #[cfg(test)]
+    mod should {
+        use super::super::parsed::{run, Stop, Vm};
+        use super::*;
+
+        fn run_handheld(input: &str) -> Result<Outcome, ProgramError> {
+            InstructionSet::handheld()
+                .read_program(input)
+                .map(|program| run(&program, RunOptions::default()).0)
+        }
+
+        #[test]
+        fn runs_handheld_programs_like_before() {
+            assert_eq!(
+                run_handheld(EXAMPLE),
+                Ok(Outcome::LoopDetected { pc: 1, acc: 5 })
+            );
+            assert_eq!(
+                run_handheld(INPUT),
+                Ok(Outcome::LoopDetected { pc: 179, acc: 1801 })
+            );
+            assert_eq!(
+                run_handheld("acc +1\njmp -2"),
+                Ok(Outcome::JumpOutOfBounds { from: 1, to: -1 })
+            );
+        }
+
+        #[test]
+        fn rejects_unknown_mnemonics() {
+            assert_eq!(
+                run_handheld("acc +1\nmul 2").map_err(|error| error.to_string()),
+                Err("Unknown operation: mul".to_string())
+            );
+            assert!(InstructionSet::extended().parse("mul 2").is_ok());
+        }
+
+        #[test]
+        fn rejects_mismatched_operands() {
+            let instructions = InstructionSet::extended();
+            for line in &["acc", "acc +1 +2", "set 1 2", "halt 0", "jnz a"] {
+                assert_eq!(
+                    instructions.parse(line).map(|_| ()),
+                    Err(ProgramError::ParseError(ParseError::FormatError)),
+                    "{}",
+                    line
+                );
+            }
+        }
+
+        #[test]
+        fn counts_down_with_registers() {
+            let program = InstructionSet::extended()
+                .read_program(
+                    "\
+set c 4
+acc +1
+mul 2
+add c -1
+jnz c -3
+halt
+acc +100",
+                )
+                .expect("Failed to read program");
+            let options = RunOptions {
+                detect_loops: false,
+                step_limit: Some(100),
+                ..RunOptions::default()
+            };
+            let (outcome, machine) = run(&program, options);
+            assert_eq!(outcome, Outcome::Terminated { acc: 30 });
+            assert_eq!(machine.registers[2], 0);
+            assert_eq!(
+                run(&program, RunOptions::default()).0,
+                Outcome::LoopDetected { pc: 1, acc: 2 }
+            );
+        }
+
+        #[test]
+        fn runs_registered_opcodes() {
+            let mut instructions = InstructionSet::handheld();
+            instructions.register(Opcode {
+                mnemonic: "neg",
+                operands: &[],
+                execute: |machine, _| {
+                    machine.accumulator =
+                        machine.accumulator.checked_neg().ok_or(Fault::Overflow)?;
+                    Ok(Flow::Next)
+                },
+            });
+            let program = instructions
+                .read_program("acc +3\nneg")
+                .expect("Failed to read program");
+            assert_eq!(
+                run(&program, RunOptions::default()).0,
+                Outcome::Terminated { acc: -3 }
+            );
+        }
+
+        #[test]
+        fn faults_on_overflow() {
+            let instructions = InstructionSet::extended();
+            for input in &[
+                "acc +9223372036854775807\nacc +2",
+                "acc +9223372036854775807\nmul 2",
+                "set a 9223372036854775807\nadd a 1",
+            ] {
+                let program = instructions
+                    .read_program(input)
+                    .expect("Failed to read program");
+                assert_eq!(
+                    run(&program, RunOptions::default()).0,
+                    Outcome::Faulted {
+                        pc: 1,
+                        fault: Fault::Overflow
+                    },
+                    "{}",
+                    input
+                );
+            }
+        }
+
+        #[test]
+        fn faults_on_writes_to_immediates() {
+            let mut instructions = InstructionSet::extended();
+            instructions.register(Opcode {
+                mnemonic: "inc",
+                operands: &[OperandKind::Value],
+                execute: |machine, operands| {
+                    *machine.register_mut(operands[0])? += 1;
+                    Ok(Flow::Next)
+                },
+            });
+            let program = instructions
+                .read_program("inc a\ninc 4")
+                .expect("Failed to read program");
+            let (outcome, machine) = run(&program, RunOptions::default());
+            assert_eq!(
+                outcome,
+                Outcome::Faulted {
+                    pc: 1,
+                    fault: Fault::NotARegister
+                }
+            );
+            assert_eq!(machine.registers[0], 1);
+        }
+
+        #[test]
+        fn debugs_extended_programs() {
+            let program = InstructionSet::extended()
+                .read_program("set b 3\nacc +1\nadd b -1\njnz b -2\nhalt\nacc +100")
+                .expect("Failed to read program");
+            let options = RunOptions {
+                detect_loops: false,
+                ..RunOptions::default()
+            };
+            let mut vm = Vm::with_options(&program, options);
+            assert!(vm.add_breakpoint(3));
+            assert_eq!(vm.run(), Stop::Breakpoint(3));
+            assert_eq!((vm.accumulator(), vm.machine().registers[1]), (1, 2));
+            assert!(vm.add_watch(3));
+            assert_eq!(vm.run(), Stop::Breakpoint(3));
+            assert_eq!(vm.run(), Stop::Watch(3));
+            assert!(vm.remove_breakpoint(3));
+            assert_eq!(vm.run(), Stop::Terminated);
+            assert_eq!((vm.pc(), vm.accumulator(), vm.steps()), (6, 3, 11));
+            assert_eq!(vm.trace().last().map(|entry| entry.pc), Some(4));
+        }
+    }
+}
+
+/// Static analysis of parsed programs, without running them.
+pub mod analysis {
+    use super::parsed::{successor, terminating, Instruction, Operation};
+    use super::*;
+    use std::ops::Range;
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum EdgeKind {
+        Fallthrough,
+        Jump,
+    }
+
+    /// Where control goes after the last instruction of a block.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Target {
+        Block(usize),
+        /// Right after the last instruction, terminating the program.
+        Exit,
+        /// A position outside the program, or `None` if it does not fit into an `isize`.
+        OutOfBounds(Option<isize>),
+    }
+
+    /// Instructions that always run in sequence, entered only at the first one.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct BasicBlock {
+        pub instructions: Range<usize>,
+        pub target: Target,
+        pub kind: EdgeKind,
+    }
+
+    /// The basic blocks of a program, each with the single edge leaving it.
+    #[derive(Clone, Debug)]
+    pub struct ControlFlowGraph<'a> {
+        program: &'a [Instruction],
+        blocks: Vec<BasicBlock>,
+        block_of: Vec<usize>,
+    }
+
+    impl<'a> ControlFlowGraph<'a> {
+        pub fn new(program: &'a [Instruction]) -> Self {
+            let mut leaders = BTreeSet::new();
+            leaders.extend((0..program.len()).take(1));
+            for (pc, instruction) in program.iter().enumerate() {
+                if instruction.operation == Operation::Jmp {
+                    leaders.extend(
+                        Some(pc + 1)
+                            .into_iter()
+                            .chain(successor(program, instruction, pc))
+                            .filter(|leader| *leader < program.len()),
+                    );
+                }
+            }
+            let starts = leaders.into_iter().collect_vec();
+            let mut block_of = vec![0; program.len()];
+            for (block, (start, end)) in starts
+                .iter()
+                .copied()
+                .chain(Some(program.len()))
+                .tuple_windows()
+                .enumerate()
+            {
+                block_of[start..end].iter_mut().for_each(|of| *of = block);
+            }
+            let blocks = starts
+                .iter()
+                .copied()
+                .chain(Some(program.len()))
+                .tuple_windows()
+                .map(|(start, end)| {
+                    let last = end - 1;
+                    let instruction = &program[last];
+                    BasicBlock {
+                        instructions: start..end,
+                        target: match successor(program, instruction, last) {
+                            Some(next) if next == program.len() => Target::Exit,
+                            Some(next) => Target::Block(block_of[next]),
+                            None => Target::OutOfBounds(instruction.target(last)),
+                        },
+                        kind: match instruction.operation {
+                            Operation::Jmp => EdgeKind::Jump,
+                            Operation::Acc | Operation::Nop => EdgeKind::Fallthrough,
+                        },
+                    }
+                })
+                .collect();
+            ControlFlowGraph {
+                program,
+                blocks,
+                block_of,
+            }
+        }
+
+        pub fn blocks(&self) -> &[BasicBlock] {
+            &self.blocks
+        }
+
+        /// The block containing the instruction.
+        pub fn block_of(&self, pc: usize) -> usize {
+            self.block_of[pc]
+        }
+
+        /// The blocks visited when running from the block, in order, until leaving the
+        /// program or reaching a block visited before.
+        fn walk(&self, start: usize) -> Vec<usize> {
+            let mut visited = vec![false; self.blocks.len()];
+            let mut path = Vec::new();
+            let mut block = start;
+            while !std::mem::replace(&mut visited[block], true) {
+                path.push(block);
+                match self.blocks[block].target {
+                    Target::Block(next) => block = next,
+                    Target::Exit | Target::OutOfBounds(_) => break,
+                }
+            }
+            path
+        }
+
+        fn instructions_of(&self, blocks: impl IntoIterator<Item = usize>) -> Vec<usize> {
+            blocks
+                .into_iter()
+                .flat_map(|block| self.blocks[block].instructions.clone())
+                .sorted()
+                .collect()
+        }
+
+        /// Instructions that never run, since no path from the start leads to them.
+        pub fn unreachable(&self) -> Vec<usize> {
+            let mut reachable = vec![false; self.blocks.len()];
+            if !self.blocks.is_empty() {
+                for block in self.walk(0) {
+                    reachable[block] = true;
+                }
+            }
+            self.instructions_of((0..self.blocks.len()).filter(|block| !reachable[*block]))
+        }
+
+        /// Instructions from which the program runs to termination.
+        pub fn terminating(&self) -> Vec<usize> {
+            terminating(self.program)
+                .into_iter()
+                .positions(|terminating| terminating)
+                .collect()
+        }
+
+        /// The instructions of each cycle of blocks, which runs forever once entered.
+        ///
+        /// Every block has a single successor, so the strongly connected components with
+        /// more than one block, or with a block jumping to itself, are exactly these cycles.
+        pub fn infinite_loops(&self) -> Vec<Vec<usize>> {
+            let mut done = vec![false; self.blocks.len()];
+            let mut position = vec![None; self.blocks.len()];
+            let mut loops = Vec::new();
+            for start in 0..self.blocks.len() {
+                let mut path = Vec::new();
+                let mut block = start;
+                while !done[block] {
+                    if let Some(index) = position[block] {
+                        loops.push(self.instructions_of(path[index..].iter().copied()));
+                        break;
+                    }
+                    position[block] = Some(path.len());
+                    path.push(block);
+                    match self.blocks[block].target {
+                        Target::Block(next) => block = next,
+                        Target::Exit | Target::OutOfBounds(_) => break,
+                    }
+                }
+                for block in path {
+                    done[block] = true;
+                }
+            }
+            loops.sort();
+            loops
+        }
+
+        /// The instructions of the infinite loop that running the program ends up in.
+        pub fn entry_loop(&self) -> Option<Vec<usize>> {
+            if self.blocks.is_empty() {
+                return None;
+            }
+            let path = self.walk(0);
+            let last = *path.last()?;
+            match self.blocks[last].target {
+                Target::Block(next) => {
+                    let start = path.iter().position(|block| *block == next)?;
+                    Some(self.instructions_of(path[start..].iter().copied()))
+                }
+                Target::Exit | Target::OutOfBounds(_) => None,
+            }
+        }
+
+        /// Writes the graph in the Graphviz DOT language, with dashed fallthrough edges and
+        /// the blocks of infinite loops in red.
+        pub fn to_dot(&self) -> String {
+            let looping: BTreeSet<usize> = self
+                .infinite_loops()
+                .into_iter()
+                .flatten()
+                .map(|pc| self.block_of(pc))
+                .collect();
+            let mut dot = String::from("digraph program {\n    node [shape=box];\n");
+            for (index, block) in self.blocks.iter().enumerate() {
+                let label: String = block
+                    .instructions
+                    .clone()
+                    .map(|pc| format!("{}: {}\\l", pc, self.program[pc]))
+                    .collect();
+                let color = if looping.contains(&index) {
+                    ", color=red"
+                } else {
+                    ""
+                };
+                dot += &format!("    b{} [label=\"{}\"{}];\n", index, label, color);
+            }
+            for (index, block) in self.blocks.iter().enumerate() {
+                let target = match block.target {
+                    Target::Block(next) => format!("b{}", next),
+                    Target::Exit => "exit".to_string(),
+                    Target::OutOfBounds(to) => {
+                        let to = to.map_or_else(|| "overflow".to_string(), |to| to.to_string());
+                        dot += &format!(
+                            "    \"out {0}\" [label=\"out of bounds: {0}\", shape=octagon];\n",
+                            to
+                        );
+                        format!("\"out {}\"", to)
+                    }
+                };
+                let style = match block.kind {
+                    EdgeKind::Fallthrough => " [style=dashed]",
+                    EdgeKind::Jump => "",
+                };
+                dot += &format!("    b{} -> {}{};\n", index, target, style);
+            }
+            if self.blocks.iter().any(|block| block.target == Target::Exit) {
+                dot += "    exit [shape=doublecircle];\n";
+            }
+            dot += "}\n";
+            dot
+        }
+    }
+
+    #[cfg(test)]
+    mod should {
+        use super::*;
+
+        fn example() -> Vec<Instruction> {
+            read_lines(EXAMPLE).expect("Failed to read example")
+        }
+
+        #[test]
+        fn splits_basic_blocks() {
+            let program = example();
+            let cfg = ControlFlowGraph::new(&program);
+            assert_eq!(
+                cfg.blocks()
+                    .iter()
+                    .map(|block| (block.instructions.clone(), block.target, block.kind))
+                    .collect_vec(),
+                vec![
+                    (0..1, Target::Block(1), EdgeKind::Fallthrough),
+                    (1..3, Target::Block(4), EdgeKind::Jump),
+                    (3..5, Target::Block(1), EdgeKind::Jump),
+                    (5..6, Target::Block(4), EdgeKind::Fallthrough),
+                    (6..8, Target::Block(2), EdgeKind::Jump),
+                    (8..9, Target::Exit, EdgeKind::Fallthrough),
+                ]
+            );
+            assert_eq!(cfg.block_of(7), 4);
+        }
+
+        #[test]
+        fn finds_unreachable_and_terminating_instructions() {
+            let program = example();
+            let cfg = ControlFlowGraph::new(&program);
+            assert_eq!(cfg.unreachable(), vec![5, 8]);
+            assert_eq!(cfg.terminating(), vec![8]);
+        }
+
+        #[test]
+        fn explains_loops() {
+            let program = example();
+            let cfg = ControlFlowGraph::new(&program);
+            assert_eq!(cfg.infinite_loops(), vec![vec![1, 2, 3, 4, 6, 7]]);
+            assert_eq!(cfg.entry_loop(), Some(vec![1, 2, 3, 4, 6, 7]));
+            let program: Vec<Instruction> = read_lines(INPUT).expect("Failed to read input");
+            let cfg = ControlFlowGraph::new(&program);
+            let entry_loop = cfg.entry_loop().expect("Input does not loop");
+            assert!(entry_loop.contains(&179));
+            assert!(cfg.infinite_loops().contains(&entry_loop));
+        }
+
+        #[test]
+        fn finds_no_entry_loop_when_leaving_the_program() {
+            let program: Vec<Instruction> =
+                read_lines("jmp +2\njmp +0\nacc +1\njmp -5").expect("Failed to read program");
+            let cfg = ControlFlowGraph::new(&program);
+            assert_eq!(cfg.entry_loop(), None);
+            assert_eq!(cfg.infinite_loops(), vec![vec![1]]);
+            assert_eq!(cfg.unreachable(), vec![1]);
+            assert_eq!(cfg.terminating(), Vec::<usize>::new());
+            assert_eq!(ControlFlowGraph::new(&[]).entry_loop(), None);
+        }
+
+        #[test]
+        fn builds_graphs_with_overflowing_jumps() {
+            let program: Vec<Instruction> =
+                read_lines("nop +0\njmp +9223372036854775807").expect("Failed to read program");
+            let cfg = ControlFlowGraph::new(&program);
+            assert_eq!(
+                cfg.blocks(),
+                &[BasicBlock {
+                    instructions: 0..2,
+                    target: Target::OutOfBounds(None),
+                    kind: EdgeKind::Jump,
+                }]
+            );
+            assert_eq!(cfg.entry_loop(), None);
+            assert!(cfg.to_dot().contains(
+                "    \"out overflow\" [label=\"out of bounds: overflow\", shape=octagon];\n"
+            ));
+        }
+
+        #[test]
+        fn exports_dot() {
+            let program: Vec<Instruction> =
+                read_lines("acc +1\njmp +2\njmp -3\nnop +0").expect("Failed to read program");
+            assert_eq!(
+                ControlFlowGraph::new(&program).to_dot(),
+                "\
+digraph program {
+    node [shape=box];
+    b0 [label=\"0: acc +1\\l1: jmp +2\\l\"];
+    b1 [label=\"2: jmp -3\\l\"];
+    b2 [label=\"3: nop +0\\l\"];
+    b0 -> b2;
+    \"out -1\" [label=\"out of bounds: -1\", shape=octagon];
+    b1 -> \"out -1\";
+    b2 -> exit [style=dashed];
+    exit [shape=doublecircle];
+}
+"
             );
         }
     }
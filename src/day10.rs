@@ -5,16 +5,15 @@ use itertools::process_results;
 use itertools::Itertools;
 
 fn find_jolt_differences(joltages: &[u32]) -> (u32, u32, u32) {
-    joltages
-        .iter()
-        .tuple_windows()
-        .map(|(a, b)| (b - a) as u32)
-        .fold((0, 0, 0), |(a, b, c), diff| match diff {
+    joltages.iter().tuple_windows().map(|(a, b)| b - a).fold(
+        (0, 0, 0),
+        |(a, b, c), diff| match diff {
             1 => (a + 1, b, c),
             2 => (a, b + 1, c),
             3 => (a, b, c + 1),
             _ => (a, b, c),
-        })
+        },
+    )
 }
 
 #[aoc_generator(day10)]
@@ -150,12 +150,12 @@ impl FromStr for Seats {
 
 impl Display for Seats {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for ch in self
-            .tiles
-            .chunks(self.columns)
-            .map(|row| String::from_utf8_lossy(row))
-            .intersperse("\n".into())
-        {
+        for ch in Itertools::intersperse(
+            self.tiles
+                .chunks(self.columns)
+                .map(|row| String::from_utf8_lossy(row)),
+            "\n".into(),
+        ) {
             write!(f, "{}", ch)?;
         }
         Ok(())
@@ -145,14 +145,14 @@ fn read_instructions(input: &str) -> Result<Vec<Instruction>, ParseError> {
 fn measure_manhattan_distance(instructions: &[Instruction]) -> usize {
     let mut position = Position::default();
     position.execute_instructions(instructions);
-    position.north.abs() as usize + position.east.abs() as usize
+    position.north.unsigned_abs() + position.east.unsigned_abs()
 }
 
 #[aoc(day12, part2)]
 fn measure_manhattan_distance_using_waypoint(instructions: &[Instruction]) -> usize {
     let mut ship = Ship::default();
     ship.execute_instructions(instructions);
-    ship.position.0.abs() as usize + ship.position.1.abs() as usize
+    ship.position.0.unsigned_abs() + ship.position.1.unsigned_abs()
 }
 
 #[derive(Debug, Eq, PartialEq)]
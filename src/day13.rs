@@ -38,6 +38,8 @@ impl Notes {
     }
 }
 
+// `usize::is_multiple_of` needs Rust 1.87
+#[allow(unknown_lints, clippy::manual_is_multiple_of)]
 fn next_departure(earliest: usize, bus_id: usize) -> usize {
     if earliest % bus_id == 0 {
         earliest
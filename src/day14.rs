@@ -173,14 +173,14 @@ fn read_program(input: &str) -> Result<Vec<Instruction>, ParseError> {
 #[aoc(day14, part1)]
 fn execute_program_with_value_masking(program: &[Instruction]) -> u64 {
     let mut computer: Computer<ValueMasking> = Computer::default();
-    computer.execute_program(&program);
+    computer.execute_program(program);
     computer.sum_memory()
 }
 
 #[aoc(day14, part2)]
 fn execute_program_with_address_decoder(program: &[Instruction]) -> u64 {
     let mut computer: Computer<AddressDecoder> = Computer::default();
-    computer.execute_program(&program);
+    computer.execute_program(program);
     computer.sum_memory()
 }
 